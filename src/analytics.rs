@@ -0,0 +1,422 @@
+use crate::tracking;
+
+use std::collections;
+use std::time;
+
+pub type Point = (f32, f32);
+
+// The point of a box that is used to locate the object.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Anchor {
+    Center,
+    // The middle of the bottom edge, which is where people and vehicles touch the ground.
+    BottomCenter,
+}
+
+impl Anchor {
+    fn point(self, tracked_box: &tracking::TrackedBox) -> Point {
+        let d = &tracked_box.detection;
+        match self {
+            Anchor::Center => ((d.x1 + d.x2) / 2.0, (d.y1 + d.y2) / 2.0),
+            Anchor::BottomCenter => ((d.x1 + d.x2) / 2.0, d.y2),
+        }
+    }
+}
+
+// A polygon area in normalized image coordinates.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Zone {
+    pub name: String,
+    pub polygon: Vec<Point>,
+}
+
+impl Zone {
+    pub fn new(name: &str, polygon: Vec<Point>) -> Self {
+        Zone {
+            name: name.to_string(),
+            polygon,
+        }
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        let (x, y) = point;
+        let mut inside = false;
+        let mut j = self.polygon.len().wrapping_sub(1);
+        for i in 0..self.polygon.len() {
+            let (xi, yi) = self.polygon[i];
+            let (xj, yj) = self.polygon[j];
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Direction {
+    // From the left to the right side of the line when looking from `start` to `end`.
+    Forward,
+    Backward,
+}
+
+// A virtual line segment in normalized image coordinates.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Tripwire {
+    pub name: String,
+    pub start: Point,
+    pub end: Point,
+}
+
+impl Tripwire {
+    pub fn new(name: &str, start: Point, end: Point) -> Self {
+        Tripwire {
+            name: name.to_string(),
+            start,
+            end,
+        }
+    }
+
+    // Returns the crossing direction if the movement from `from` to `to` crosses the line.
+    //
+    // A point exactly on the line counts as being on its right side, so a track stopping on
+    // the line is counted once, either when it reaches the line or when it leaves to the left.
+    pub fn crossing(&self, from: Point, to: Point) -> Option<Direction> {
+        let side_from = side(self.start, self.end, from);
+        let side_to = side(self.start, self.end, to);
+        if (side_from >= 0.0) == (side_to >= 0.0) {
+            return None;
+        }
+
+        // The movement must also cross the line segment itself, not its extension.
+        let side_start = side(from, to, self.start);
+        let side_end = side(from, to, self.end);
+        if (side_start > 0.0) == (side_end > 0.0) && side_start != 0.0 && side_end != 0.0 {
+            return None;
+        }
+
+        // Image coordinates grow downwards, so a positive cross product is the right side.
+        if side_to >= 0.0 {
+            Some(Direction::Forward)
+        } else {
+            Some(Direction::Backward)
+        }
+    }
+}
+
+fn side(a: Point, b: Point, p: Point) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum EventKind {
+    Enter,
+    Exit,
+    Cross(Direction),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Event {
+    pub timestamp: time::Duration,
+    pub track_id: u64,
+    pub label: String,
+    // The name of the zone or the tripwire.
+    pub source: String,
+    pub kind: EventKind,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct CountKey {
+    pub source: String,
+    pub label: String,
+}
+
+#[derive(PartialEq, Eq, Default, Clone, Debug)]
+pub struct Counts {
+    pub entered: u64,
+    pub exited: u64,
+    pub forward: u64,
+    pub backward: u64,
+}
+
+impl Counts {
+    fn add(&mut self, kind: &EventKind) {
+        match kind {
+            EventKind::Enter => self.entered += 1,
+            EventKind::Exit => self.exited += 1,
+            EventKind::Cross(Direction::Forward) => self.forward += 1,
+            EventKind::Cross(Direction::Backward) => self.backward += 1,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct IntervalCounts {
+    pub start: time::Duration,
+    pub counts: collections::HashMap<CountKey, Counts>,
+}
+
+struct TrackState {
+    point: Point,
+    last_seen: time::Duration,
+    zones: Vec<bool>,
+}
+
+// Counts tracked objects entering and leaving zones and crossing tripwires.
+//
+// The first position of a track only establishes whether it is inside each zone, so objects
+// that are already in a zone when they appear are not counted as entering it. Tracks that
+// have not been seen for `forget_after` are dropped.
+pub struct Counter {
+    zones: Vec<Zone>,
+    tripwires: Vec<Tripwire>,
+    anchor: Anchor,
+    interval: time::Duration,
+    forget_after: time::Duration,
+    tracks: collections::HashMap<u64, TrackState>,
+    totals: collections::HashMap<CountKey, Counts>,
+    intervals: Vec<IntervalCounts>,
+}
+
+impl Counter {
+    pub fn new(interval: time::Duration) -> Self {
+        Counter {
+            zones: Vec::new(),
+            tripwires: Vec::new(),
+            anchor: Anchor::BottomCenter,
+            interval,
+            forget_after: time::Duration::from_secs(10),
+            tracks: collections::HashMap::new(),
+            totals: collections::HashMap::new(),
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn zone(mut self, zone: Zone) -> Self {
+        self.zones.push(zone);
+        self
+    }
+
+    pub fn tripwire(mut self, tripwire: Tripwire) -> Self {
+        self.tripwires.push(tripwire);
+        self
+    }
+
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn forget_after(mut self, forget_after: time::Duration) -> Self {
+        self.forget_after = forget_after;
+        self
+    }
+
+    // Consumes the tracked boxes of the next frame and returns the events it produced.
+    pub fn update(
+        &mut self,
+        timestamp: time::Duration,
+        tracked_boxes: &[tracking::TrackedBox],
+    ) -> Vec<Event> {
+        let forget_after = self.forget_after;
+        self.tracks
+            .retain(|_, state| timestamp <= state.last_seen + forget_after);
+
+        let mut events = Vec::new();
+        for tracked_box in tracked_boxes.iter() {
+            let point = self.anchor.point(tracked_box);
+            let zones: Vec<bool> = self.zones.iter().map(|zone| zone.contains(point)).collect();
+            let mut push_event = |source: &str, kind: EventKind| {
+                events.push(Event {
+                    timestamp,
+                    track_id: tracked_box.id,
+                    label: tracked_box.detection.label.clone(),
+                    source: source.to_string(),
+                    kind,
+                })
+            };
+
+            if let Some(state) = self.tracks.get(&tracked_box.id) {
+                for (zone, (was_inside, is_inside)) in
+                    zip!(self.zones.iter(), state.zones.iter(), zones.iter())
+                {
+                    if !was_inside && *is_inside {
+                        push_event(&zone.name, EventKind::Enter);
+                    } else if *was_inside && !is_inside {
+                        push_event(&zone.name, EventKind::Exit);
+                    }
+                }
+                for tripwire in self.tripwires.iter() {
+                    if let Some(direction) = tripwire.crossing(state.point, point) {
+                        push_event(&tripwire.name, EventKind::Cross(direction));
+                    }
+                }
+            }
+
+            self.tracks.insert(
+                tracked_box.id,
+                TrackState {
+                    point,
+                    last_seen: timestamp,
+                    zones,
+                },
+            );
+        }
+
+        for event in events.iter() {
+            self.count(event);
+        }
+
+        events
+    }
+
+    fn count(&mut self, event: &Event) {
+        let key = CountKey {
+            source: event.source.clone(),
+            label: event.label.clone(),
+        };
+        self.totals
+            .entry(key.clone())
+            .or_insert_with(Counts::default)
+            .add(&event.kind);
+
+        let interval_nanos = self.interval.as_nanos().max(1);
+        let start_nanos = event.timestamp.as_nanos() / interval_nanos * interval_nanos;
+        let start = time::Duration::from_nanos(start_nanos as u64);
+        let position = match self.intervals.binary_search_by_key(&start, |i| i.start) {
+            Ok(position) => position,
+            Err(position) => {
+                self.intervals.insert(
+                    position,
+                    IntervalCounts {
+                        start,
+                        counts: collections::HashMap::new(),
+                    },
+                );
+                position
+            }
+        };
+        self.intervals[position]
+            .counts
+            .entry(key)
+            .or_insert_with(Counts::default)
+            .add(&event.kind);
+    }
+
+    // Returns the counts since the counter was created.
+    pub fn totals(&self) -> &collections::HashMap<CountKey, Counts> {
+        &self.totals
+    }
+
+    // Returns the counts aggregated per interval, ordered by the interval start.
+    pub fn intervals(&self) -> &[IntervalCounts] {
+        &self.intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection;
+
+    fn tracked(id: u64, label: &str, x: f32, y: f32) -> tracking::TrackedBox {
+        tracking::TrackedBox {
+            id,
+            detection: detection::DetectionBox {
+                x1: x - 0.05,
+                y1: y - 0.05,
+                x2: x + 0.05,
+                y2: y + 0.05,
+                label: label.to_string(),
+                score: 1.0,
+            },
+        }
+    }
+
+    fn key(source: &str, label: &str) -> CountKey {
+        CountKey {
+            source: source.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn zone_contains() {
+        let zone = Zone::new("lot", vec![(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (0.0, 0.5)]);
+        assert!(zone.contains((0.25, 0.25)));
+        assert!(!zone.contains((0.75, 0.25)));
+    }
+
+    #[test]
+    fn tripwire_crossing() {
+        let tripwire = Tripwire::new("gate", (0.5, 0.0), (0.5, 1.0));
+        assert_eq!(
+            tripwire.crossing((0.4, 0.5), (0.6, 0.5)),
+            Some(Direction::Backward)
+        );
+        assert_eq!(
+            tripwire.crossing((0.6, 0.5), (0.4, 0.5)),
+            Some(Direction::Forward)
+        );
+        assert_eq!(tripwire.crossing((0.4, 0.5), (0.45, 0.5)), None);
+        assert_eq!(tripwire.crossing((0.4, 1.5), (0.6, 1.5)), None);
+    }
+
+    #[test]
+    fn tripwire_crossing_through_line() {
+        let tripwire = Tripwire::new("gate", (0.5, 0.0), (0.5, 1.0));
+        let crossings = |path: &[Point]| -> Vec<Direction> {
+            path.windows(2)
+                .filter_map(|pair| tripwire.crossing(pair[0], pair[1]))
+                .collect()
+        };
+
+        // The centre lands exactly on the line.
+        let path = [(0.4, 0.5), (0.5, 0.5), (0.6, 0.5)];
+        assert_eq!(crossings(&path), vec![Direction::Backward]);
+        let path = [(0.6, 0.5), (0.5, 0.5), (0.4, 0.5)];
+        assert_eq!(crossings(&path), vec![Direction::Forward]);
+        let path = [(0.4, 0.5), (0.5, 0.5), (0.5, 0.6), (0.6, 0.6)];
+        assert_eq!(crossings(&path), vec![Direction::Backward]);
+    }
+
+    #[test]
+    fn counts_zone_and_tripwire_events() {
+        let mut counter = Counter::new(time::Duration::from_secs(60))
+            .anchor(Anchor::Center)
+            .zone(Zone::new(
+                "lot",
+                vec![(0.5, 0.0), (1.0, 0.0), (1.0, 1.0), (0.5, 1.0)],
+            ))
+            .tripwire(Tripwire::new("gate", (0.5, 0.0), (0.5, 1.0)));
+
+        let frames = [(50, 0.3), (55, 0.45), (61, 0.7), (62, 0.4)];
+        let mut events = Vec::new();
+        for (secs, x) in frames.iter() {
+            let timestamp = time::Duration::from_secs(*secs);
+            events.extend(counter.update(timestamp, &[tracked(12, "car", *x, 0.5)]));
+        }
+
+        let kinds: Vec<EventKind> = events.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::Enter,
+                EventKind::Cross(Direction::Backward),
+                EventKind::Exit,
+                EventKind::Cross(Direction::Forward),
+            ]
+        );
+
+        let totals = counter.totals();
+        assert_eq!(totals[&key("lot", "car")].entered, 1);
+        assert_eq!(totals[&key("lot", "car")].exited, 1);
+        assert_eq!(totals[&key("gate", "car")].forward, 1);
+        assert_eq!(totals[&key("gate", "car")].backward, 1);
+
+        let intervals = counter.intervals();
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].start, time::Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::cache;
+use crate::detection;
+use crate::error;
+use crate::ssd_mobilenet;
+
+use std::io;
+use std::path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task;
+
+// Errors are passed back as messages because `error::Error` is not `Send`.
+type Runner =
+    dyn Fn(&image::DynamicImage) -> Result<Vec<detection::DetectionBox>, String> + Send + Sync;
+
+// Runs SSD MobileNet detections from async code without blocking the runtime.
+//
+// Every `session.run` call is offloaded to the tokio blocking thread pool. The number of
+// detections that are queued or running at the same time is bounded by `queue_depth`, so
+// callers awaiting `detect` are held back once the queue is full.
+pub struct AsyncDetector {
+    run: Arc<Runner>,
+    queue: Arc<Semaphore>,
+}
+
+impl AsyncDetector {
+    pub fn new(ssd_net: ssd_mobilenet::SSDMobileNetV2, queue_depth: usize) -> Self {
+        AsyncDetector::with_runner(
+            move |img| ssd_net.shot(img).map_err(|err| err.to_string()),
+            queue_depth,
+        )
+    }
+
+    fn with_runner<F>(run: F, queue_depth: usize) -> Self
+    where
+        F: Fn(&image::DynamicImage) -> Result<Vec<detection::DetectionBox>, String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        AsyncDetector {
+            run: Arc::new(run),
+            queue: Arc::new(Semaphore::new(queue_depth.max(1))),
+        }
+    }
+
+    // Loads the graph on the blocking thread pool and wraps it into a detector.
+    pub async fn load(
+        ssd_graph_path: &path::Path,
+        queue_depth: usize,
+    ) -> Result<Self, error::Error> {
+        let ssd_graph_path = ssd_graph_path.to_path_buf();
+        let ssd_net = task::spawn_blocking(move || {
+            ssd_mobilenet::SSDMobileNetV2::load(&ssd_graph_path).map_err(|err| err.to_string())
+        })
+        .await
+        .map_err(io::Error::from)??;
+
+        Ok(AsyncDetector::new(ssd_net, queue_depth))
+    }
+
+    // Waits for a free slot in the queue and runs the detection on the blocking thread pool.
+    pub async fn detect(
+        &self,
+        img: image::DynamicImage,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let permit = self.queue.clone().acquire_owned().await;
+        self.spawn(img, permit).await
+    }
+
+    // Same as `detect`, but fails immediately instead of waiting when the queue is full.
+    pub async fn try_detect(
+        &self,
+        img: image::DynamicImage,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let permit = self
+            .queue
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| "The detection queue is full")?;
+        self.spawn(img, permit).await
+    }
+
+    // Returns the number of detections that can be queued without waiting.
+    pub fn available_slots(&self) -> usize {
+        self.queue.available_permits()
+    }
+
+    async fn spawn(
+        &self,
+        img: image::DynamicImage,
+        permit: tokio::sync::OwnedSemaphorePermit,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let run = self.run.clone();
+
+        // The permit is moved into the task, so the slot stays taken until the session run
+        // completes even if the caller drops the returned future. A panic in the task comes
+        // back as a `JoinError`.
+        let boxes = task::spawn_blocking(move || {
+            let boxes = run(&img);
+            std::mem::drop(permit);
+            boxes
+        })
+        .await
+        .map_err(io::Error::from)??;
+
+        Ok(boxes)
+    }
+}
+
+// An async variant of `cache::get_or_load_ssd_mobilenet_v2_graph`.
+pub async fn get_or_load_ssd_mobilenet_v2_graph_async() -> Result<path::PathBuf, error::Error> {
+    let ssd_graph = task::spawn_blocking(|| {
+        cache::get_or_load_ssd_mobilenet_v2_graph().map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(io::Error::from)??;
+
+    Ok(ssd_graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time;
+
+    #[tokio::test]
+    async fn limit_concurrent_detections() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let (counter, max_counter) = (running.clone(), max_running.clone());
+        let detector = Arc::new(AsyncDetector::with_runner(
+            move |_| {
+                let current = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                max_counter.fetch_max(current, Ordering::SeqCst);
+                thread::sleep(time::Duration::from_millis(20));
+                counter.fetch_sub(1, Ordering::SeqCst);
+                Ok(Vec::new())
+            },
+            2,
+        ));
+
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                let detector = detector.clone();
+                tokio::spawn(async move {
+                    detector
+                        .detect(image::DynamicImage::new_rgb8(1, 1))
+                        .await
+                        .map_err(|err| err.to_string())
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), Ok(Vec::new()));
+        }
+
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+        assert_eq!(detector.available_slots(), 2);
+    }
+
+    #[tokio::test]
+    async fn return_task_failures() {
+        let detector = AsyncDetector::with_runner(|_| Err("Session failed".to_string()), 1);
+        let err = detector
+            .detect(image::DynamicImage::new_rgb8(1, 1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Session failed");
+
+        let detector = AsyncDetector::with_runner(|_| panic!("Session panicked"), 1);
+        assert!(detector
+            .detect(image::DynamicImage::new_rgb8(1, 1))
+            .await
+            .is_err());
+        // The slot is released even though the task panicked.
+        assert_eq!(detector.available_slots(), 1);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::detection;
+use crate::error;
+use crate::ssd_mobilenet;
+
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time;
+
+// Errors are passed back to the callers as messages because `error::Error` is not `Send`.
+type Reply = Result<Vec<detection::DetectionBox>, String>;
+
+struct Request {
+    img: image::DynamicImage,
+    reply: mpsc::Sender<Reply>,
+}
+
+// Collects concurrent single-image detections into batches.
+//
+// A background worker waits for the first request, then keeps collecting requests until
+// either `max_batch_size` images are queued or `max_latency` has passed, runs the whole batch
+// as one tensor and sends each image's boxes back to its caller.
+pub struct BatchingDetector {
+    sender: Option<Mutex<mpsc::Sender<Request>>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl BatchingDetector {
+    pub fn new(
+        ssd_net: ssd_mobilenet::SSDMobileNetV2,
+        max_batch_size: usize,
+        max_latency: time::Duration,
+    ) -> Self {
+        BatchingDetector::with_runner(
+            move |imgs| ssd_net.shot_batch(imgs),
+            max_batch_size,
+            max_latency,
+        )
+    }
+
+    fn with_runner<F>(run_batch: F, max_batch_size: usize, max_latency: time::Duration) -> Self
+    where
+        F: Fn(&[&image::DynamicImage]) -> Result<Vec<Vec<detection::DetectionBox>>, error::Error>
+            + Send
+            + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let max_batch_size = max_batch_size.max(1);
+        let worker = thread::spawn(move || {
+            while let Some(batch) = collect_batch(&receiver, max_batch_size, max_latency) {
+                let imgs: Vec<&image::DynamicImage> = batch.iter().map(|r| &r.img).collect();
+                let results = run_batch(&imgs);
+                reply_batch(batch, results);
+            }
+        });
+
+        BatchingDetector {
+            sender: Some(Mutex::new(sender)),
+            worker: Some(worker),
+        }
+    }
+
+    // Queues the image for the next batch and blocks until its boxes are available.
+    pub fn detect(
+        &self,
+        img: image::DynamicImage,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let (reply, response) = mpsc::channel();
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.lock().ok())
+            .ok_or_else(|| "The batching worker is not available")?
+            .send(Request { img, reply })
+            .map_err(|_| "The batching worker has stopped")?;
+
+        let boxes = response
+            .recv()
+            .map_err(|_| "The batching worker has stopped")??;
+
+        Ok(boxes)
+    }
+}
+
+impl Drop for BatchingDetector {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker once the already queued requests are served.
+        std::mem::drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("The batching worker has panicked");
+            }
+        }
+    }
+}
+
+// Waits for the first request and collects the following ones within the latency window.
+//
+// Returns `None` once all senders are dropped and the queue is empty.
+fn collect_batch(
+    receiver: &mpsc::Receiver<Request>,
+    max_batch_size: usize,
+    max_latency: time::Duration,
+) -> Option<Vec<Request>> {
+    let first = receiver.recv().ok()?;
+    let deadline = time::Instant::now() + max_latency;
+
+    let mut batch = vec![first];
+    while batch.len() < max_batch_size {
+        let now = time::Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match receiver.recv_timeout(deadline - now) {
+            Ok(request) => batch.push(request),
+            Err(_) => break,
+        }
+    }
+
+    Some(batch)
+}
+
+// Fans the batch results back out to the callers.
+fn reply_batch(
+    batch: Vec<Request>,
+    results: Result<Vec<Vec<detection::DetectionBox>>, error::Error>,
+) {
+    match results {
+        Ok(boxes) if boxes.len() == batch.len() => {
+            for (request, boxes) in batch.into_iter().zip(boxes.into_iter()) {
+                let _ = request.reply.send(Ok(boxes));
+            }
+        }
+        Ok(boxes) => {
+            let message = format!(
+                "The batch returned {} results for {} images",
+                boxes.len(),
+                batch.len()
+            );
+            for request in batch {
+                let _ = request.reply.send(Err(message.clone()));
+            }
+        }
+        Err(err) => {
+            let message = err.to_string();
+            for request in batch {
+                let _ = request.reply.send(Err(message.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use std::sync::Arc;
+
+    fn fake_box(width: u32) -> detection::DetectionBox {
+        detection::DetectionBox {
+            x1: 0.0,
+            y1: 0.0,
+            x2: width as f32,
+            y2: 1.0,
+            label: "car".to_string(),
+            score: 1.0,
+        }
+    }
+
+    #[test]
+    fn batches_concurrent_requests() {
+        let (batch_sizes_tx, batch_sizes_rx) = mpsc::channel();
+        let detector = Arc::new(BatchingDetector::with_runner(
+            move |imgs| {
+                batch_sizes_tx.send(imgs.len()).unwrap();
+                Ok(imgs.iter().map(|img| vec![fake_box(img.width())]).collect())
+            },
+            4,
+            time::Duration::from_millis(500),
+        ));
+
+        let handles: Vec<_> = (1..=4)
+            .map(|width| {
+                let detector = detector.clone();
+                thread::spawn(move || {
+                    let img = image::DynamicImage::new_rgb8(width, 1);
+                    detector.detect(img).unwrap()
+                })
+            })
+            .collect();
+
+        for (width, handle) in (1..=4).zip(handles) {
+            assert_eq!(handle.join().unwrap(), vec![fake_box(width)]);
+        }
+        let batch_sizes: Vec<usize> = batch_sizes_rx.try_iter().collect();
+        assert_eq!(batch_sizes.iter().sum::<usize>(), 4);
+        assert!(batch_sizes.len() < 4);
+    }
+
+    #[test]
+    fn fans_out_batch_errors() {
+        let detector = BatchingDetector::with_runner(
+            |_| Err("Session failed".into()),
+            2,
+            time::Duration::from_millis(1),
+        );
+        let err = detector
+            .detect(image::DynamicImage::new_rgb8(1, 1))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Session failed");
+    }
+}
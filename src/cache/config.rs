@@ -0,0 +1,740 @@
+use super::download;
+use super::extract;
+use super::lock;
+use super::manage;
+use super::manifest;
+use super::registry;
+use super::signature;
+use crate::error;
+use crate::ms_coco;
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+
+// Overrides the models cache directory.
+pub const CACHE_DIR_ENV: &str = "IMAGE_SSD_CACHE";
+// Enables the offline mode when set to `1` or `true`.
+pub const OFFLINE_ENV: &str = "IMAGE_SSD_OFFLINE";
+// Enables the verification of cached models on load when set to `1` or `true`.
+pub const VERIFY_ENV: &str = "IMAGE_SSD_VERIFY";
+// Limits the size of the models cache in bytes.
+pub const MAX_SIZE_ENV: &str = "IMAGE_SSD_MAX_SIZE";
+// Lists the minisign public key files trusted to sign model manifests.
+pub const TRUSTED_KEYS_ENV: &str = "IMAGE_SSD_TRUSTED_KEYS";
+// Allows downloading models without a checksum when set to `1` or `true`.
+pub const ALLOW_UNVERIFIED_ENV: &str = "IMAGE_SSD_ALLOW_UNVERIFIED";
+
+// The file written into a model directory once it is completely unpacked.
+pub(crate) const COMPLETE_MARKER: &str = ".complete";
+
+// Configures where models are cached and how they are downloaded.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    pub dir: path::PathBuf,
+    // Never touch the network, only use models that are already in the cache.
+    pub offline: bool,
+    // Check the model files against the manifest every time a model is returned from the cache.
+    pub verify_on_load: bool,
+    // Evict the least recently used models once a new model makes the cache larger than this.
+    pub max_size: Option<u64>,
+    // When set, every model must have a manifest signed by one of these keys, which is
+    // verified with the model files every time the model is returned from the cache.
+    pub trusted_keys: Vec<signature::PublicKey>,
+    // Download models that have neither a SHA-256 checksum nor a manifest signed by a trusted
+    // key. Such downloads are refused by default.
+    pub allow_unverified: bool,
+    pub download: download::DownloadOptions,
+    pub extract: extract::ExtractLimits,
+}
+
+impl CacheConfig {
+    pub fn new(dir: &path::Path) -> Self {
+        CacheConfig {
+            dir: dir.to_path_buf(),
+            offline: false,
+            verify_on_load: false,
+            max_size: None,
+            trusted_keys: Vec::new(),
+            allow_unverified: false,
+            download: download::DownloadOptions::default(),
+            extract: extract::ExtractLimits::default(),
+        }
+    }
+
+    // Returns the configuration from the environment.
+    //
+    // The cache directory is taken from `IMAGE_SSD_CACHE`, then `$XDG_CACHE_HOME/image_ssd/models`,
+    // then `~/.cache/image_ssd/models`. The offline mode is enabled by `IMAGE_SSD_OFFLINE`,
+    // the verification on load by `IMAGE_SSD_VERIFY`, the size limit is set by
+    // `IMAGE_SSD_MAX_SIZE`, the trusted keys are loaded from the files listed in
+    // `IMAGE_SSD_TRUSTED_KEYS` and models without checksums are allowed by
+    // `IMAGE_SSD_ALLOW_UNVERIFIED`.
+    pub fn from_env() -> Result<Self, error::Error> {
+        let max_size = match env::var(MAX_SIZE_ENV) {
+            Ok(value) if !value.is_empty() => Some(value.parse::<u64>().map_err(|_| {
+                format!(
+                    "{} must be a number of bytes, got \"{}\"",
+                    MAX_SIZE_ENV, value
+                )
+            })?),
+            _ => None,
+        };
+
+        let mut config = CacheConfig::new(&default_cache_dir()?)
+            .offline(env_flag(OFFLINE_ENV))
+            .verify_on_load(env_flag(VERIFY_ENV))
+            .max_size(max_size)
+            .allow_unverified(env_flag(ALLOW_UNVERIFIED_ENV));
+        if let Some(key_paths) = env::var_os(TRUSTED_KEYS_ENV) {
+            for key_path in env::split_paths(&key_paths).filter(|path| !path.as_os_str().is_empty())
+            {
+                config = config.trusted_key(signature::PublicKey::from_file(&key_path)?);
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn verify_on_load(mut self, verify_on_load: bool) -> Self {
+        self.verify_on_load = verify_on_load;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn trusted_key(mut self, key: signature::PublicKey) -> Self {
+        self.trusted_keys.push(key);
+        self
+    }
+
+    pub fn allow_unverified(mut self, allow_unverified: bool) -> Self {
+        self.allow_unverified = allow_unverified;
+        self
+    }
+
+    pub fn download(mut self, options: download::DownloadOptions) -> Self {
+        self.download = options;
+        self
+    }
+
+    pub fn extract(mut self, limits: extract::ExtractLimits) -> Self {
+        self.extract = limits;
+        self
+    }
+
+    // Returns a directory the model is unpacked into. The model ID is checked by the methods
+    // accessing it.
+    pub fn model_dir(&self, model: &registry::ModelDescriptor) -> path::PathBuf {
+        self.dir.join(&model.id)
+    }
+
+    // Returns a path to the model graph file located in the cache.
+    pub fn get_model(
+        &self,
+        model: &registry::ModelDescriptor,
+    ) -> Result<path::PathBuf, error::Error> {
+        manage::check_model_id(&model.id)?;
+        let dir = self.model_dir(model);
+        let graph = dir.join(&model.graph_path);
+
+        if !dir.join(COMPLETE_MARKER).exists() || !graph.exists() {
+            Err(error::Error::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} graph file not found by path \"{}\". Please consider to download it manually from \"{}\".",
+                    model.name, graph.display(), model.urls.join("\", \"")
+                ),
+            )))
+        } else {
+            if self.verify_on_load || !self.trusted_keys.is_empty() {
+                self.verify_model(model)?;
+            }
+            manage::touch(&dir.join(COMPLETE_MARKER));
+            Ok(graph)
+        }
+    }
+
+    // Checks the cached model files against the manifest written when the model was unpacked
+    // or the one signed by the model publisher.
+    //
+    // Fails if the model is not cached, has no manifest or any of its files is missing or has
+    // been modified. With trusted keys it also fails if the manifest is not signed by one of
+    // them. A failed model is downloaded again by `get_or_load_model` when the verification on
+    // load is enabled.
+    pub fn verify_model(&self, model: &registry::ModelDescriptor) -> Result<(), error::Error> {
+        manage::check_model_id(&model.id)?;
+        let dir = self.model_dir(model);
+        if !dir.join(COMPLETE_MARKER).exists() {
+            return Err(error::Error::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not cached in \"{}\".", model.name, dir.display()),
+            )));
+        }
+
+        self.verify_dir(model, &dir)
+    }
+
+    fn verify_dir(
+        &self,
+        model: &registry::ModelDescriptor,
+        dir: &path::Path,
+    ) -> Result<(), error::Error> {
+        let manifest_path = dir.join(manifest::MANIFEST_FILE);
+        let raw_manifest = fs::read(&manifest_path)?;
+
+        if !self.trusted_keys.is_empty() {
+            let signature_path = signature::signature_file_path(&manifest_path);
+            if !signature_path.exists() {
+                return Err(error::Error::SignatureError(error::SignatureError {
+                    message: format!("The manifest of {} is not signed.", model.name),
+                }));
+            }
+            signature::Signature::from_file(&signature_path)?
+                .verify(&raw_manifest, &self.trusted_keys)?;
+        }
+
+        let raw_manifest =
+            String::from_utf8(raw_manifest).map_err(|_| "The manifest is not valid UTF-8")?;
+        manifest::Manifest::parse(&raw_manifest)?.verify_files(dir, &model.files())
+    }
+
+    // Returns a path to the model graph file and download it if a file not exist.
+    //
+    // The population is guarded by an inter-process lock, so concurrent callers wait for the
+    // first one instead of downloading the model again. The archive is unpacked into a
+    // temporary directory which is marked as complete and atomically renamed into place, so a
+    // model directory is never observed half-extracted.
+    pub fn get_or_load_model(
+        &self,
+        model: &registry::ModelDescriptor,
+    ) -> Result<path::PathBuf, error::Error> {
+        // The model directory is replaced below, so its ID must not point outside of the cache.
+        manage::check_model_id(&model.id)?;
+        if let Ok(graph) = self.get_model(model) {
+            return Ok(graph);
+        }
+        if self.offline {
+            return Err(error::Error::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} is not cached in \"{}\" and the offline mode is enabled. Please consider to unpack \"{}\" into \"{}\" and create the \"{}\" file there.",
+                    model.name,
+                    self.dir.display(),
+                    model.urls.join("\", \""),
+                    self.model_dir(model).display(),
+                    COMPLETE_MARKER
+                ),
+            )));
+        }
+
+        if model.manifest_url.is_none() && !self.trusted_keys.is_empty() {
+            return Err(error::Error::SignatureError(error::SignatureError {
+                message: format!(
+                    "{} has no signed manifest, but only signed models are allowed.",
+                    model.name
+                ),
+            }));
+        }
+        let signed = model.manifest_url.is_some() && !self.trusted_keys.is_empty();
+        if model.sha256.is_none() && !signed && !self.allow_unverified {
+            return Err(format!(
+                "{} has no SHA-256 checksum, so the download can not be verified. Please consider to set its checksum or allow unverified models with {}=1.",
+                model.name, ALLOW_UNVERIFIED_ENV
+            )
+            .into());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let lock = lock::FileLock::acquire(&self.lock_path(&model.id))?;
+
+        // Another process could have populated the cache while this one was waiting for the lock.
+        if let Ok(graph) = self.get_model(model) {
+            return Ok(graph);
+        }
+
+        let dir = self.model_dir(model);
+        let tmp_dir = TmpDir::new(
+            self.dir
+                .join(format!(".{}.tmp-{}", model.id, process::id())),
+        );
+        let archive_file_name = model.archive_file_name()?;
+        // Archives are prefixed by the model ID, so `clean` finds them by the model lock.
+        let archive = self
+            .dir
+            .join(format!(".{}.{}", model.id, archive_file_name));
+
+        info!("Downloading {}...", archive.display());
+        download::download(
+            &archive,
+            &model.urls,
+            model.sha256.as_deref(),
+            &self.download,
+        )?;
+
+        info!("Unpacking {}...", archive.display());
+        if tmp_dir.path.exists() {
+            fs::remove_dir_all(&tmp_dir.path)?;
+        }
+        fs::create_dir_all(&tmp_dir.path)?;
+        extract::unpack(
+            &archive,
+            model.archive_format()?,
+            &tmp_dir.path,
+            &model.files(),
+            &self.extract,
+        )?;
+
+        for file_path in model.files() {
+            if !tmp_dir.path.join(file_path).exists() {
+                return Err(error::Error::IoError(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!(
+                        "Could not find \"{}\" file in \"{}\".",
+                        file_path,
+                        archive.display()
+                    ),
+                )));
+            }
+        }
+        match model.manifest_url {
+            Some(ref manifest_url) => {
+                let manifest_path = tmp_dir.path.join(manifest::MANIFEST_FILE);
+                download::download(
+                    &manifest_path,
+                    &[manifest_url.to_string()],
+                    None,
+                    &self.download,
+                )?;
+                if let Some(ref signature_url) = model.signature_url {
+                    download::download(
+                        &signature::signature_file_path(&manifest_path),
+                        &[signature_url.to_string()],
+                        None,
+                        &self.download,
+                    )?;
+                }
+                if self.trusted_keys.is_empty() {
+                    warn!(
+                        "No trusted keys are configured, skipping the verification of the {} manifest signature.",
+                        model.name
+                    );
+                }
+                // A model which fails the verification is never marked as complete.
+                self.verify_dir(model, &tmp_dir.path)?;
+            }
+            None => manifest::Manifest::create(&tmp_dir.path)?.save(&tmp_dir.path)?,
+        }
+        fs::write(tmp_dir.path.join(COMPLETE_MARKER), &archive_file_name)?;
+
+        // A directory without the marker is left by an interrupted run of an older version.
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        tmp_dir.rename(&dir)?;
+
+        info!("Download complete.");
+        fs::remove_file(archive)?;
+        let graph = self.get_model(model)?;
+
+        // Other models are evicted under their own locks, so this one is released first to
+        // never wait for a lock while holding another.
+        std::mem::drop(lock);
+        if let Some(max_size) = self.max_size {
+            if let Err(err) = self.prune_except(max_size, Some(&model.id)) {
+                warn!("Failed to prune the models cache: {}", err);
+            }
+        }
+
+        Ok(graph)
+    }
+
+    // Returns a path to the graph file of a known model located in the cache.
+    pub fn get(&self, model_id: &str) -> Result<path::PathBuf, error::Error> {
+        self.get_model(&registry::find_model(model_id)?)
+    }
+
+    // Returns a path to the graph file of a known model and download it if a file not exist.
+    pub fn get_or_load(&self, model_id: &str) -> Result<path::PathBuf, error::Error> {
+        self.get_or_load_model(&registry::find_model(model_id)?)
+    }
+
+    // Checks the files of a known model located in the cache.
+    pub fn verify(&self, model_id: &str) -> Result<(), error::Error> {
+        self.verify_model(&registry::find_model(model_id)?)
+    }
+
+    // Returns the label map of the model.
+    //
+    // The model must be already cached if it ships its own label map.
+    pub fn get_label_map(
+        &self,
+        model: &registry::ModelDescriptor,
+    ) -> Result<ms_coco::LabelMap, error::Error> {
+        match model.label_map {
+            Some(ref label_map) => {
+                manage::check_model_id(&model.id)?;
+                ms_coco::LabelMap::from_file(&self.model_dir(model).join(label_map))
+            }
+            None => ms_coco::LabelMap::load(),
+        }
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+// Returns the models cache directory resolved from the environment.
+pub fn default_cache_dir() -> Result<path::PathBuf, error::Error> {
+    cache_dir_from(
+        env::var_os(CACHE_DIR_ENV),
+        env::var_os("XDG_CACHE_HOME"),
+        dirs::home_dir(),
+    )
+}
+
+fn cache_dir_from(
+    cache_dir: Option<std::ffi::OsString>,
+    xdg_cache_home: Option<std::ffi::OsString>,
+    home_dir: Option<path::PathBuf>,
+) -> Result<path::PathBuf, error::Error> {
+    if let Some(cache_dir) = cache_dir.filter(|dir| !dir.is_empty()) {
+        return Ok(path::PathBuf::from(cache_dir));
+    }
+
+    // Relative XDG paths are invalid according to the specification and must be ignored.
+    let xdg_cache_home = xdg_cache_home
+        .map(path::PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    if let Some(xdg_cache_home) = xdg_cache_home {
+        return Ok(xdg_cache_home.join("image_ssd/models"));
+    }
+
+    match home_dir {
+        Some(home_dir) => Ok(home_dir.join(".cache/image_ssd/models")),
+        None => Err(format!(
+            "Impossible to get your home dir! Please set {} to the models cache directory.",
+            CACHE_DIR_ENV
+        )
+        .into()),
+    }
+}
+
+// Removes the directory a model is unpacked into unless it was moved into the cache, so
+// failed downloads leave no partially unpacked files behind.
+struct TmpDir {
+    path: path::PathBuf,
+    renamed: bool,
+}
+
+impl TmpDir {
+    fn new(path: path::PathBuf) -> Self {
+        TmpDir {
+            path,
+            renamed: false,
+        }
+    }
+
+    fn rename(mut self, dir: &path::Path) -> Result<(), error::Error> {
+        fs::rename(&self.path, dir)?;
+        self.renamed = true;
+        Ok(())
+    }
+}
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        if !self.renamed && self.path.exists() {
+            if let Err(err) = fs::remove_dir_all(&self.path) {
+                warn!("Failed to remove {}: {}", self.path.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_cache_dir() {
+        let home = Some(path::PathBuf::from("/home/user"));
+        assert_eq!(
+            cache_dir_from(Some("/cache".into()), Some("/xdg".into()), home.clone()).unwrap(),
+            path::PathBuf::from("/cache")
+        );
+        assert_eq!(
+            cache_dir_from(None, Some("/xdg".into()), home.clone()).unwrap(),
+            path::PathBuf::from("/xdg/image_ssd/models")
+        );
+        assert_eq!(
+            cache_dir_from(Some("".into()), Some("xdg".into()), home).unwrap(),
+            path::PathBuf::from("/home/user/.cache/image_ssd/models")
+        );
+        assert!(cache_dir_from(None, None, None).is_err());
+    }
+
+    fn graph_archive() -> Vec<u8> {
+        let mut tar_builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        tar_builder
+            .append_data(&mut header, "model/graph.pb", &b"graph"[..])
+            .unwrap();
+        tar_builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    // Returns a cache in an empty temporary directory and a model it downloads from memory.
+    // The returned source can serve more files.
+    fn cache_fixture(
+        name: &str,
+    ) -> (
+        CacheConfig,
+        registry::ModelDescriptor,
+        super::super::source::MemorySource,
+    ) {
+        let base_dir = env::temp_dir().join(format!("image_ssd_{}", name));
+        let _ = fs::remove_dir_all(&base_dir);
+
+        let source = super::super::source::MemorySource::new()
+            .file("https://example.com/model.tar.gz", graph_archive());
+        let config = CacheConfig::new(&base_dir)
+            .download(download::DownloadOptions::default().source(source.clone()));
+        let model = registry::ModelDescriptor::new(
+            "model",
+            "https://example.com/model.tar.gz",
+            "model/graph.pb",
+        );
+
+        (config, model, source)
+    }
+
+    #[test]
+    fn requires_complete_marker() {
+        let (config, model, _) = cache_fixture("requires_complete_marker");
+        let graph = config.model_dir(&model).join(&model.graph_path);
+        fs::create_dir_all(graph.parent().unwrap()).unwrap();
+        fs::write(&graph, b"graph").unwrap();
+
+        assert!(config.get_model(&model).is_err());
+        fs::File::create(config.model_dir(&model).join(COMPLETE_MARKER)).unwrap();
+        assert_eq!(config.get_model(&model).unwrap(), graph);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn verify_on_load() {
+        let (config, model, _) = cache_fixture("verify_on_load");
+        let config = config.verify_on_load(true);
+        let dir = config.model_dir(&model);
+        let graph = dir.join(&model.graph_path);
+        fs::create_dir_all(graph.parent().unwrap()).unwrap();
+        fs::write(&graph, b"graph").unwrap();
+        fs::File::create(dir.join(COMPLETE_MARKER)).unwrap();
+
+        // Models cached without a manifest can not be verified.
+        assert!(config.get_model(&model).is_err());
+
+        manifest::Manifest::create(&dir)
+            .unwrap()
+            .save(&dir)
+            .unwrap();
+        assert!(config.get_model(&model).is_ok());
+
+        fs::write(&graph, b"grapH").unwrap();
+        assert!(config.get_model(&model).is_err());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn offline_mode_does_not_download() {
+        let (config, model, _) = cache_fixture("offline_mode_does_not_download");
+        let config = config.allow_unverified(true).offline(true);
+
+        let err = config.get_or_load_model(&model).unwrap_err();
+        assert!(err.to_string().contains("offline mode"));
+        assert!(!config.dir.exists());
+    }
+
+    #[test]
+    fn reject_traversal_ids() {
+        let (config, _, _) = cache_fixture("reject_traversal_ids");
+        let config = config.allow_unverified(true);
+        let outside_dir = env::temp_dir().join("image_ssd_reject_traversal_ids_outside");
+        fs::create_dir_all(&outside_dir).unwrap();
+        fs::write(outside_dir.join("data.txt"), b"data").unwrap();
+
+        let outside_id = outside_dir.to_string_lossy().to_string();
+        let ids = [
+            "../image_ssd_reject_traversal_ids_outside",
+            "..",
+            "a/b",
+            "a\\b",
+            outside_id.as_str(),
+            "",
+        ];
+        for id in ids.iter() {
+            let model = registry::ModelDescriptor::new(
+                id,
+                "https://example.com/model.tar.gz",
+                "model/graph.pb",
+            );
+            assert!(config.get_or_load_model(&model).is_err(), "{}", id);
+            assert!(config.get_model(&model).is_err(), "{}", id);
+            assert!(config.verify_model(&model).is_err(), "{}", id);
+        }
+        assert_eq!(fs::read(outside_dir.join("data.txt")).unwrap(), b"data");
+
+        fs::remove_dir_all(&outside_dir).unwrap();
+        let _ = fs::remove_dir_all(&config.dir);
+    }
+
+    #[test]
+    fn load_local_model() {
+        let base_dir = env::temp_dir().join("image_ssd_load_local_model");
+        let _ = fs::remove_dir_all(&base_dir);
+        let source_path = env::temp_dir().join("image_ssd_load_local_model.pb");
+        fs::write(&source_path, b"abc").unwrap();
+
+        let config = CacheConfig::new(&base_dir).verify_on_load(true);
+        let model = registry::ModelDescriptor::new(
+            "local",
+            &format!("file://{}", source_path.display()),
+            "model.pb",
+        )
+        .sha256("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let graph = config.get_or_load_model(&model).unwrap();
+        assert_eq!(graph, base_dir.join("local/model.pb"));
+        assert_eq!(fs::read(&graph).unwrap(), b"abc");
+        assert!(config.verify_model(&model).is_ok());
+
+        fs::remove_file(&source_path).unwrap();
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn load_model_from_source() {
+        let (config, model, _) = cache_fixture("load_model_from_source");
+        let config = config.allow_unverified(true);
+
+        let graph = config.get_or_load_model(&model).unwrap();
+        assert_eq!(fs::read(&graph).unwrap(), b"graph");
+        assert!(config.verify_model(&model).is_ok());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn remove_tmp_dir_on_error() {
+        let (config, model, _) = cache_fixture("remove_tmp_dir_on_error");
+        let config = config.allow_unverified(true);
+        let tmp_dirs = || {
+            fs::read_dir(&config.dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .filter(|file_name| file_name.contains(".tmp-"))
+                .count()
+        };
+
+        let mut missing_file = model.clone();
+        missing_file.graph_path = "model/missing.pb".to_string();
+        assert!(config.get_or_load_model(&missing_file).is_err());
+        assert_eq!(tmp_dirs(), 0);
+
+        let limited_config = config
+            .clone()
+            .extract(extract::ExtractLimits::default().max_total_size(1));
+        assert!(limited_config.get_or_load_model(&model).is_err());
+        assert_eq!(tmp_dirs(), 0);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn require_checksums() {
+        let (config, model, _) = cache_fixture("require_checksums");
+
+        let err = config.get_or_load_model(&model).unwrap_err();
+        assert!(err.to_string().contains("no SHA-256 checksum"));
+        assert!(!config.dir.exists());
+        for model in registry::models() {
+            if model.sha256.is_none() {
+                assert!(config.get_or_load_model(&model).is_err());
+            }
+        }
+        assert!(!config.dir.exists());
+
+        let config = config.allow_unverified(true);
+        let graph = config.get_or_load_model(&model).unwrap();
+        assert_eq!(fs::read(&graph).unwrap(), b"graph");
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn load_signed_model() {
+        let manifest =
+            "eef93e1d14482804277fca0172464032d1a4fdbcc338524059fa1e861454ad4d  model/graph.pb\n";
+        let signature = "untrusted comment: signature\n\
+            RUQBAgMEBQYHCAe7I+qiwNOGn9FoHXf9Bsb0y2vHIASffv9uJr1RpI3GaEQ1Iu9Wr2XQ1z7Jk7RUAvC/wGLc4PPOT3x1Kx7h9QU=\n\
+            trusted comment: model v1\n\
+            gonNe62/CZrvsejODCXQl8bgDDnK2SxwVcp1kGKtLfL2tUZiLCJewweT1wnQXIb6cfsMYp8Hdz1fLF0YsKxPDg==\n";
+        let key =
+            signature::PublicKey::parse("RWQBAgMEBQYHCOpKbGPinFIKvvVQexMuxfmVR3auvr57kkIe6mkURtIs")
+                .unwrap();
+
+        let (config, model, source) = cache_fixture("load_signed_model");
+        let config = config.trusted_key(key);
+        source.insert(
+            "https://example.com/tampered.sha256",
+            manifest.replace("eef9", "eef8").into_bytes(),
+        );
+        source.insert(
+            "https://example.com/model.sha256",
+            manifest.as_bytes().to_vec(),
+        );
+        source.insert(
+            "https://example.com/model.sha256.minisig",
+            signature.as_bytes().to_vec(),
+        );
+
+        match config.get_or_load_model(&model) {
+            Err(error::Error::SignatureError(_)) => (),
+            _ => panic!("Expected unsigned models to be rejected"),
+        }
+        let tampered_model = model
+            .clone()
+            .signed_manifest("https://example.com/tampered.sha256")
+            .signature("https://example.com/model.sha256.minisig");
+        assert!(config.get_or_load_model(&tampered_model).is_err());
+
+        let model = model.signed_manifest("https://example.com/model.sha256");
+        let graph = config.get_or_load_model(&model).unwrap();
+        assert_eq!(fs::read(&graph).unwrap(), b"graph");
+
+        // The manifest is checked against the signature on every load.
+        let manifest_path = config.model_dir(&model).join(manifest::MANIFEST_FILE);
+        fs::write(&manifest_path, format!("{}\n", manifest)).unwrap();
+        assert!(config.get_model(&model).is_err());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
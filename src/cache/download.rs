@@ -0,0 +1,352 @@
+use super::source;
+use crate::error;
+use sha2::Digest;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path;
+use std::sync::Arc;
+use std::thread;
+use std::time;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Receives the number of downloaded bytes and the total size of the file if it is known.
+pub type ProgressCallback = Arc<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct DownloadOptions {
+    // The number of retries for every mirror after the first attempt fails.
+    pub retries: u32,
+    // The delay before the first retry. It is doubled after every failed retry.
+    pub backoff: time::Duration,
+    pub timeout: time::Duration,
+    pub progress: Option<ProgressCallback>,
+    // Fetches the files instead of the built-in HTTP and filesystem sources.
+    pub source: Option<Arc<dyn source::ModelSource>>,
+}
+
+impl DownloadOptions {
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(u64, Option<u64>) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    pub fn source<S: source::ModelSource + 'static>(mut self, source: S) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            retries: 2,
+            backoff: time::Duration::from_secs(1),
+            timeout: time::Duration::from_secs(120),
+            progress: None,
+            source: None,
+        }
+    }
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DownloadOptions {{ retries: {}, backoff: {:?}, timeout: {:?}, progress: {}, source: {}}}",
+            self.retries,
+            self.backoff,
+            self.timeout,
+            self.progress.is_some(),
+            self.source.is_some()
+        )
+    }
+}
+
+// Downloads the file from the first mirror that succeeds, retrying every mirror with an
+// exponential backoff.
+//
+// The data is streamed into a `.part` file next to `file_path`, which is renamed once its
+// checksum is verified. A `.part` file left by an interrupted attempt is resumed with an HTTP
+// range request. Without a checksum a stale or foreign `.part` file could not be detected, so
+// the download is started over instead. `file://` URLs and plain paths are copied from the
+// filesystem and are not retried unless a custom source is set.
+pub(crate) fn download(
+    file_path: &path::Path,
+    urls: &[String],
+    sha256sum: Option<&str>,
+    options: &DownloadOptions,
+) -> Result<(), error::Error> {
+    let mut failures = Vec::new();
+
+    for url in urls.iter() {
+        let mut backoff = options.backoff;
+        let retries = if options.source.is_none() && source::local_path(url).is_some() {
+            0
+        } else {
+            options.retries
+        };
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                warn!("Retrying {} in {:?}...", url, backoff);
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+
+            match download_file(file_path, url, sha256sum, options) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    warn!("Failed to download {}: {}", url, err);
+                    failures.push((url.to_string(), err.to_string()));
+                }
+            }
+        }
+    }
+
+    Err(error::Error::DownloadError(error::DownloadError {
+        failures,
+    }))
+}
+
+// Returns the path of the partially downloaded file.
+pub(crate) fn part_file_path(file_path: &path::Path) -> path::PathBuf {
+    let mut file_name = file_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    file_path.with_file_name(file_name)
+}
+
+// Streams the file into its `.part` file and moves it to `file_path` once verified.
+fn download_file(
+    file_path: &path::Path,
+    url: &str,
+    sha256sum: Option<&str>,
+    options: &DownloadOptions,
+) -> Result<(), error::Error> {
+    let part_path = part_file_path(file_path);
+    if sha256sum.is_none() && part_path.exists() {
+        fs::remove_file(&part_path)?;
+    }
+    let hasher = fetch_part(url, &part_path, options)?;
+
+    let archive_sha256 = format!("{:x}", hasher.finalize());
+    match sha256sum {
+        Some(sha256sum) if archive_sha256 != sha256sum => {
+            // The data is corrupted, so it can not be resumed by the next attempt.
+            fs::remove_file(&part_path)?;
+            return Err(error::Error::ChecksumMismatch(error::ChecksumMismatch {
+                source: url.to_string(),
+                expected: sha256sum.to_string(),
+                actual: archive_sha256,
+            }));
+        }
+        Some(_) => (),
+        None => warn!(
+            "No checksum is known for \"{}\", skipping the verification.",
+            url
+        ),
+    }
+
+    fs::rename(&part_path, file_path)?;
+
+    Ok(())
+}
+
+// Streams the file into the `.part` file, resuming it if it exists, and returns the hasher
+// fed with the whole file.
+fn fetch_part(
+    url: &str,
+    part_path: &path::Path,
+    options: &DownloadOptions,
+) -> Result<sha2::Sha256, error::Error> {
+    let mut hasher = sha2::Sha256::new();
+    let mut offset = hash_file(part_path, &mut hasher).unwrap_or_else(|_| {
+        hasher = sha2::Sha256::new();
+        0
+    });
+    if offset > 0 {
+        info!("Resuming {} from {} bytes...", url, offset);
+    }
+
+    let fetched = source::fetch(options.source.as_ref(), url, offset, options.timeout)?;
+    if !fetched.resumed {
+        offset = 0;
+        hasher = sha2::Sha256::new();
+    }
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
+        .open(part_path)?;
+    let mut writer = io::BufWriter::new(file);
+    let mut reader = fetched.reader;
+    let mut downloaded = offset;
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&chunk[..read])?;
+        hasher.update(&chunk[..read]);
+        downloaded += read as u64;
+        report_progress(options, downloaded, fetched.total);
+    }
+    writer.flush()?;
+
+    if let Some(total) = fetched.total {
+        if downloaded != total {
+            return Err(format!("Downloaded {} of {} bytes", downloaded, total).into());
+        }
+    }
+
+    Ok(hasher)
+}
+
+fn report_progress(options: &DownloadOptions, downloaded: u64, total: Option<u64>) {
+    if let Some(ref progress) = options.progress {
+        progress(downloaded, total);
+    }
+}
+
+// Feeds the file into the hasher and returns its size.
+pub(crate) fn hash_file(file_path: &path::Path, hasher: &mut sha2::Sha256) -> io::Result<u64> {
+    let mut file = fs::File::open(file_path)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(size);
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_mirror_failures() {
+        let file_path = std::env::temp_dir().join("image_ssd_aggregates_mirror_failures.tar.gz");
+        let urls = vec![
+            "http://127.0.0.1:9/first.tar.gz".to_string(),
+            "http://127.0.0.1:9/second.tar.gz".to_string(),
+        ];
+        let options = DownloadOptions {
+            retries: 1,
+            backoff: time::Duration::from_millis(1),
+            timeout: time::Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        match download(&file_path, &urls, None, &options) {
+            Err(error::Error::DownloadError(err)) => {
+                let failed_urls: Vec<&str> =
+                    err.failures.iter().map(|(url, _)| url.as_str()).collect();
+                let (first, second) = (urls[0].as_str(), urls[1].as_str());
+                assert_eq!(failed_urls, vec![first, first, second, second]);
+            }
+            _ => panic!("Expected a download error"),
+        }
+    }
+
+    #[test]
+    fn part_file_path_appends_suffix() {
+        assert_eq!(
+            part_file_path(path::Path::new("/tmp/models/model.tar.gz")),
+            path::Path::new("/tmp/models/model.tar.gz.part")
+        );
+    }
+
+    #[test]
+    fn hash_existing_file() {
+        let file_path = std::env::temp_dir().join("image_ssd_hash_existing_file");
+        fs::write(&file_path, b"abc").unwrap();
+
+        let mut hasher = sha2::Sha256::new();
+        assert_eq!(hash_file(&file_path, &mut hasher).unwrap(), 3);
+        assert_eq!(
+            format!("{:x}", hasher.finalize()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn copy_local_file() {
+        let source_path = std::env::temp_dir().join("image_ssd_copy_local_file.pb");
+        let file_path = std::env::temp_dir().join("image_ssd_copy_local_file.copy.pb");
+        fs::write(&source_path, b"abc").unwrap();
+        let url = format!("file://{}", source_path.display());
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let urls = vec![url];
+        assert!(download(
+            &file_path,
+            &urls,
+            Some(&sha256.replace('b', "c")),
+            &Default::default()
+        )
+        .is_err());
+        assert!(!file_path.exists());
+        download(&file_path, &urls, Some(sha256), &Default::default()).unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"abc");
+
+        fs::remove_file(&source_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn resume_partial_file() {
+        let file_path = std::env::temp_dir().join("image_ssd_resume_partial_file.pb");
+        fs::write(part_file_path(&file_path), b"ab").unwrap();
+        let source = source::MemorySource::new().file("memory://model.pb", b"abc".to_vec());
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = progress.clone();
+        let options = DownloadOptions::default()
+            .source(source)
+            .progress(move |downloaded, total| reported.lock().unwrap().push((downloaded, total)));
+
+        download(
+            &file_path,
+            &["memory://model.pb".to_string()],
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"abc");
+        assert_eq!(*progress.lock().unwrap(), vec![(3, Some(3))]);
+
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn restart_unverified_partial_file() {
+        let file_path = std::env::temp_dir().join("image_ssd_restart_unverified_partial_file.pb");
+        // A stale partial file larger than the source file.
+        fs::write(part_file_path(&file_path), b"abcd").unwrap();
+        let source = source::MemorySource::new().file("memory://model.pb", b"abc".to_vec());
+        let options = DownloadOptions::default().source(source);
+
+        download(
+            &file_path,
+            &["memory://model.pb".to_string()],
+            None,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"abc");
+
+        fs::remove_file(&file_path).unwrap();
+    }
+}
@@ -0,0 +1,482 @@
+use crate::error;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+// The format of a downloaded model file.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarXz,
+    Tar,
+    Zip,
+    // A bare model file, like a `.pb` graph or a `.tflite` model.
+    Raw,
+}
+
+impl ArchiveFormat {
+    // Detects the format by the file extension. Unknown files are treated as bare model files.
+    //
+    // The query and fragment of a URL are ignored.
+    pub fn from_file_name(file_name: &str) -> Self {
+        let file_name = strip_url_query(file_name).to_lowercase();
+        if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if file_name.ends_with(".tar.xz") || file_name.ends_with(".txz") {
+            ArchiveFormat::TarXz
+        } else if file_name.ends_with(".tar") {
+            ArchiveFormat::Tar
+        } else if file_name.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else {
+            ArchiveFormat::Raw
+        }
+    }
+}
+
+// Returns the URL without its query and fragment, e.g. the access token of a signed URL.
+pub(crate) fn strip_url_query(url: &str) -> &str {
+    url.split(|c| c == '?' || c == '#').next().unwrap_or(url)
+}
+
+// Bounds the resources an archive may take when it is unpacked.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ExtractLimits {
+    // The maximum total size of the archive entries in bytes.
+    pub max_total_size: u64,
+    // The maximum number of the archive entries.
+    pub max_files: usize,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        ExtractLimits {
+            max_total_size: 2 * 1024 * 1024 * 1024,
+            max_files: 10_000,
+        }
+    }
+}
+
+impl ExtractLimits {
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+}
+
+// Unpacks `files` of the archive into `base_dir`.
+//
+// Archives come from user-configured URLs, so entries with absolute paths or `..` components
+// and links pointing outside of the archive are rejected instead of being skipped. The limits
+// are checked for each entry before it is written, so the entries preceding the one exceeding
+// them are left in `base_dir`. Links to extracted files are replaced by copies of these files.
+// A bare model file is copied to the only wanted path.
+pub(crate) fn unpack(
+    file_path: &path::Path,
+    format: ArchiveFormat,
+    base_dir: &path::Path,
+    files: &[&str],
+    limits: &ExtractLimits,
+) -> Result<(), error::Error> {
+    let file = fs::File::open(file_path)?;
+    match format {
+        ArchiveFormat::TarGz => {
+            unpack_tar(flate2::read::GzDecoder::new(file), base_dir, files, limits)
+        }
+        ArchiveFormat::TarXz => {
+            unpack_tar(xz2::read::XzDecoder::new(file), base_dir, files, limits)
+        }
+        ArchiveFormat::Tar => unpack_tar(file, base_dir, files, limits),
+        ArchiveFormat::Zip => unpack_zip(file, base_dir, files, limits),
+        ArchiveFormat::Raw => {
+            let model_path = match files {
+                [model_path] => *model_path,
+                _ => {
+                    return Err(unsafe_archive(format!(
+                        "A bare model file can not provide {}.",
+                        files.join(", ")
+                    )))
+                }
+            };
+            let mut unpacker = Unpacker::new(base_dir, &[model_path], limits)?;
+            let size = file.metadata()?.len();
+            let entry_path = unpacker.check_entry(path::Path::new(model_path), size)?;
+            unpacker.write_file(&entry_path, file, size)
+        }
+    }
+}
+
+pub(crate) fn unpack_tar<R: Read>(
+    reader: R,
+    base_dir: &path::Path,
+    files: &[&str],
+    limits: &ExtractLimits,
+) -> Result<(), error::Error> {
+    let mut unpacker = Unpacker::new(base_dir, files, limits)?;
+    let mut tar_archive = tar::Archive::new(reader);
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let size = entry.header().size()?;
+        let entry_path = unpacker.check_entry(&entry.path()?, size)?;
+        let entry_type = entry.header().entry_type();
+
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let link_name = entry
+                .link_name()?
+                .ok_or_else(|| unsafe_archive(format!("The link {} has no target.", entry_path)))?;
+            // Symbolic links are relative to their directory, hard links to the archive root.
+            unpacker.add_link(entry_path, &link_name, entry_type.is_symlink())?;
+        } else if entry_type.is_file() {
+            unpacker.write_file(&entry_path, &mut entry, size)?;
+        }
+    }
+
+    unpacker.finish()
+}
+
+pub(crate) fn unpack_zip<R: Read + io::Seek>(
+    reader: R,
+    base_dir: &path::Path,
+    files: &[&str],
+    limits: &ExtractLimits,
+) -> Result<(), error::Error> {
+    let mut unpacker = Unpacker::new(base_dir, files, limits)?;
+    let mut zip_archive = zip::ZipArchive::new(reader).map_err(zip_error)?;
+    for index in 0..zip_archive.len() {
+        let mut entry = zip_archive.by_index(index).map_err(zip_error)?;
+        let size = entry.size();
+        let entry_path = unpacker.check_entry(path::Path::new(entry.name()), size)?;
+
+        let is_symlink = entry
+            .unix_mode()
+            .map(|mode| mode & 0o170_000 == 0o120_000)
+            .unwrap_or(false);
+        if is_symlink {
+            // Zip archives store the target of a symbolic link as its content.
+            let mut link_name = String::new();
+            entry.by_ref().take(size).read_to_string(&mut link_name)?;
+            unpacker.add_link(entry_path, path::Path::new(&link_name), true)?;
+        } else if !entry.is_dir() {
+            unpacker.write_file(&entry_path, &mut entry, size)?;
+        }
+    }
+
+    unpacker.finish()
+}
+
+// Checks the archive entries and writes the wanted ones.
+struct Unpacker<'a> {
+    base_dir: &'a path::Path,
+    wanted: Vec<String>,
+    limits: &'a ExtractLimits,
+    files: usize,
+    total_size: u64,
+    links: Vec<(String, String)>,
+}
+
+impl<'a> Unpacker<'a> {
+    fn new(
+        base_dir: &'a path::Path,
+        files: &[&str],
+        limits: &'a ExtractLimits,
+    ) -> Result<Self, error::Error> {
+        let wanted = files
+            .iter()
+            .map(|file_path| normalize(path::Path::new(file_path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if wanted.iter().any(|file_path| file_path.is_empty()) {
+            return Err("The model file paths must not be empty".into());
+        }
+
+        Ok(Unpacker {
+            base_dir,
+            wanted,
+            limits,
+            files: 0,
+            total_size: 0,
+            links: Vec::new(),
+        })
+    }
+
+    // Accounts the entry against the limits and returns its normalized path.
+    fn check_entry(&mut self, entry_path: &path::Path, size: u64) -> Result<String, error::Error> {
+        self.files += 1;
+        if self.files > self.limits.max_files {
+            return Err(unsafe_archive(format!(
+                "The archive has more than {} entries.",
+                self.limits.max_files
+            )));
+        }
+
+        self.total_size = self.total_size.saturating_add(size);
+        if self.total_size > self.limits.max_total_size {
+            return Err(unsafe_archive(format!(
+                "The archive is larger than {} bytes.",
+                self.limits.max_total_size
+            )));
+        }
+
+        normalize(entry_path)
+    }
+
+    fn write_file<R: Read>(
+        &self,
+        entry_path: &str,
+        reader: R,
+        size: u64,
+    ) -> Result<(), error::Error> {
+        if !self.wanted.iter().any(|wanted| wanted == entry_path) {
+            return Ok(());
+        }
+
+        let file_path = self.base_dir.join(entry_path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(&file_path)?;
+        // The declared size is already checked against the limits, so the data can not
+        // exceed it.
+        io::copy(&mut reader.take(size), &mut file)?;
+
+        Ok(())
+    }
+
+    fn add_link(
+        &mut self,
+        entry_path: String,
+        link_name: &path::Path,
+        relative_to_parent: bool,
+    ) -> Result<(), error::Error> {
+        let target = if relative_to_parent {
+            let parent = path::Path::new(&entry_path)
+                .parent()
+                .unwrap_or_else(|| path::Path::new(""));
+            normalize(&parent.join(link_name))?
+        } else {
+            normalize(link_name)?
+        };
+        if self.wanted.contains(&entry_path) {
+            self.links.push((entry_path, target));
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), error::Error> {
+        for (link_path, target) in self.links {
+            let target_path = self.base_dir.join(&target);
+            if !target_path.is_file() {
+                return Err(unsafe_archive(format!(
+                    "The link {} points to {} which is not extracted.",
+                    link_path, target
+                )));
+            }
+            let link_path = self.base_dir.join(&link_path);
+            if let Some(parent) = link_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(target_path, link_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the `/`-separated path without `.` components, failing if it is absolute or leaves
+// its base directory. The base directory itself is an empty path.
+pub(crate) fn normalize(file_path: &path::Path) -> Result<String, error::Error> {
+    let mut components: Vec<String> = Vec::new();
+    for component in file_path.components() {
+        match component {
+            path::Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+            path::Component::CurDir => (),
+            path::Component::ParentDir => {
+                if components.pop().is_none() {
+                    return Err(unsafe_archive(format!(
+                        "The path {} points outside of the archive.",
+                        file_path.display()
+                    )));
+                }
+            }
+            path::Component::RootDir | path::Component::Prefix(_) => {
+                return Err(unsafe_archive(format!(
+                    "The path {} is absolute.",
+                    file_path.display()
+                )))
+            }
+        }
+    }
+
+    Ok(components.join("/"))
+}
+
+fn unsafe_archive(message: String) -> error::Error {
+    error::Error::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn zip_error(err: zip::result::ZipError) -> error::Error {
+    match err {
+        zip::result::ZipError::Io(err) => error::Error::IoError(err),
+        err => unsafe_archive(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_tar(build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        builder.into_inner().unwrap()
+    }
+
+    fn append_file(builder: &mut tar::Builder<Vec<u8>>, file_path: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        // `set_path` refuses `..`, so the name is written as is to build malicious archives.
+        header.as_gnu_mut().unwrap().name[..file_path.len()].copy_from_slice(file_path.as_bytes());
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    fn append_link(builder: &mut tar::Builder<Vec<u8>>, file_path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_path(file_path).unwrap();
+        header.as_gnu_mut().unwrap().linkname[..target.len()].copy_from_slice(target.as_bytes());
+        header.set_cksum();
+        builder.append(&header, io::empty()).unwrap();
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(path::Path::new("./model/./graph.pb")).unwrap(),
+            "model/graph.pb"
+        );
+        assert_eq!(normalize(path::Path::new("a/../b")).unwrap(), "b");
+        assert!(normalize(path::Path::new("../graph.pb")).is_err());
+        assert!(normalize(path::Path::new("/etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn extract_wanted_files_only() {
+        let base_dir = std::env::temp_dir().join("image_ssd_extract_wanted_files_only");
+        let _ = fs::remove_dir_all(&base_dir);
+        let archive = build_tar(|builder| {
+            append_file(builder, "./model/graph.pb", b"graph");
+            append_file(builder, "model/checkpoint", b"checkpoint");
+            append_link(builder, "model/graph_link.pb", "graph.pb");
+        });
+
+        unpack_tar(
+            &archive[..],
+            &base_dir,
+            &["model/graph.pb", "model/graph_link.pb"],
+            &ExtractLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(base_dir.join("model/graph.pb")).unwrap(), b"graph");
+        assert_eq!(
+            fs::read(base_dir.join("model/graph_link.pb")).unwrap(),
+            b"graph"
+        );
+        assert!(!base_dir.join("model/checkpoint").exists());
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(
+            ArchiveFormat::from_file_name("model.tar.gz"),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("model.TXZ"),
+            ArchiveFormat::TarXz
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("model.zip"),
+            ArchiveFormat::Zip
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("model.tflite"),
+            ArchiveFormat::Raw
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("https://example.com/model.zip?token=abc#files"),
+            ArchiveFormat::Zip
+        );
+    }
+
+    #[test]
+    fn extract_zip() {
+        let base_dir = std::env::temp_dir().join("image_ssd_extract_zip");
+        let _ = fs::remove_dir_all(&base_dir);
+        let mut zip_writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        zip_writer
+            .start_file("model/graph.pb", zip::write::FileOptions::default())
+            .unwrap();
+        io::Write::write_all(&mut zip_writer, b"graph").unwrap();
+        zip_writer
+            .start_file("../graph.pb", zip::write::FileOptions::default())
+            .unwrap();
+        let archive = zip_writer.finish().unwrap().into_inner();
+
+        let limits = ExtractLimits::default();
+        assert!(unpack_zip(
+            io::Cursor::new(&archive),
+            &base_dir,
+            &["model/graph.pb"],
+            &limits
+        )
+        .is_err());
+        assert_eq!(fs::read(base_dir.join("model/graph.pb")).unwrap(), b"graph");
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn reject_unsafe_archives() {
+        let base_dir = std::env::temp_dir().join("image_ssd_reject_unsafe_archives");
+        let limits = ExtractLimits::default();
+
+        let traversal = build_tar(|builder| append_file(builder, "../graph.pb", b"graph"));
+        assert!(unpack_tar(&traversal[..], &base_dir, &["graph.pb"], &limits).is_err());
+
+        let escaping_link =
+            build_tar(|builder| append_link(builder, "graph.pb", "../../etc/passwd"));
+        assert!(unpack_tar(&escaping_link[..], &base_dir, &["graph.pb"], &limits).is_err());
+
+        let archive = build_tar(|builder| {
+            append_file(builder, "a.pb", b"graph");
+            append_file(builder, "b.pb", b"graph");
+        });
+        assert!(unpack_tar(
+            &archive[..],
+            &base_dir,
+            &["a.pb"],
+            &limits.clone().max_files(1)
+        )
+        .is_err());
+        assert!(unpack_tar(
+            &archive[..],
+            &base_dir,
+            &["a.pb"],
+            &limits.max_total_size(8)
+        )
+        .is_err());
+
+        let _ = fs::remove_dir_all(&base_dir);
+    }
+}
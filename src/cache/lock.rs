@@ -0,0 +1,64 @@
+use crate::error;
+use fs2::FileExt;
+use std::fs;
+use std::path;
+
+// An exclusive inter-process lock held on a file until it is dropped.
+pub(crate) struct FileLock {
+    file: fs::File,
+}
+
+impl FileLock {
+    // Blocks until the lock on `lock_path` is acquired, creating the file if needed.
+    pub(crate) fn acquire(lock_path: &path::Path) -> Result<Self, error::Error> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(lock_path)?;
+
+        if file.try_lock_exclusive().is_err() {
+            info!("Waiting for {} to be unlocked...", lock_path.display());
+            file.lock_exclusive()?;
+        }
+
+        Ok(FileLock { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(err) = self.file.unlock() {
+            warn!("Failed to unlock the cache: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time;
+
+    #[test]
+    fn blocks_until_released() {
+        let lock_path = std::env::temp_dir().join("image_ssd_blocks_until_released.lock");
+        let lock = FileLock::acquire(&lock_path).unwrap();
+
+        let (acquired_tx, acquired_rx) = mpsc::channel();
+        let waiter_lock_path = lock_path.clone();
+        let waiter = thread::spawn(move || {
+            let _lock = FileLock::acquire(&waiter_lock_path).unwrap();
+            acquired_tx.send(()).unwrap();
+        });
+
+        assert!(acquired_rx
+            .recv_timeout(time::Duration::from_millis(100))
+            .is_err());
+        std::mem::drop(lock);
+        assert!(acquired_rx
+            .recv_timeout(time::Duration::from_secs(5))
+            .is_ok());
+        waiter.join().unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+use super::config::{CacheConfig, COMPLETE_MARKER};
+use super::lock;
+use super::registry;
+use crate::error;
+use std::fs;
+use std::io;
+use std::path;
+use std::time;
+
+// Describes a model directory found in the cache.
+#[derive(PartialEq, Clone, Debug)]
+pub struct CachedModel {
+    pub id: String,
+    pub path: path::PathBuf,
+    // The total size of the model files in bytes.
+    pub size: u64,
+    // The name of the archive the model was unpacked from. The TensorFlow zoo archive names
+    // carry the release date of the model.
+    pub version: Option<String>,
+    // When the model was last returned from the cache.
+    pub last_used: time::SystemTime,
+    // Whether the model was completely unpacked. Incomplete models are left by interrupted
+    // downloads and are removed by `CacheConfig::clean`.
+    pub complete: bool,
+}
+
+impl CacheConfig {
+    // Returns the models located in the cache sorted by ID.
+    //
+    // Incomplete directories are only listed for the registry models and the models that
+    // were downloaded to the cache, so unrelated directories are never pruned.
+    pub fn list(&self) -> Result<Vec<CachedModel>, error::Error> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let known_ids = self.known_ids()?;
+        let mut models = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().to_string();
+            if id.starts_with('.') || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let model = cached_model(id, entry.path())?;
+            if model.complete || known_ids.contains(&model.id) {
+                models.push(model);
+            }
+        }
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(models)
+    }
+
+    // Removes the model and its partial downloads from the cache.
+    //
+    // Returns `false` if the model was not cached.
+    pub fn remove(&self, model_id: &str) -> Result<bool, error::Error> {
+        check_model_id(model_id)?;
+
+        let dir = self.dir.join(model_id);
+        if !self.dir.exists() {
+            return Ok(false);
+        }
+        let _lock = lock::FileLock::acquire(&self.lock_path(model_id))?;
+
+        let mut removed = false;
+        for leftover in self.leftovers(model_id)? {
+            remove_path(&leftover)?;
+        }
+        if dir.exists() {
+            info!("Removing {}...", dir.display());
+            fs::remove_dir_all(&dir)?;
+            removed = true;
+        }
+
+        Ok(removed)
+    }
+
+    // Removes the least recently used models until the cache takes at most `max_size` bytes.
+    //
+    // Incomplete models are removed first. Returns the removed models.
+    pub fn prune(&self, max_size: u64) -> Result<Vec<CachedModel>, error::Error> {
+        self.prune_except(max_size, None)
+    }
+
+    pub(crate) fn prune_except(
+        &self,
+        max_size: u64,
+        keep: Option<&str>,
+    ) -> Result<Vec<CachedModel>, error::Error> {
+        let mut models = self.list()?;
+        let mut total_size: u64 = models.iter().map(|model| model.size).sum();
+        models.sort_by_key(|model| (model.complete, model.last_used));
+
+        let mut removed = Vec::new();
+        for model in models {
+            if total_size <= max_size {
+                break;
+            }
+            if Some(model.id.as_str()) == keep {
+                continue;
+            }
+            if self.remove(&model.id)? {
+                total_size = total_size.saturating_sub(model.size);
+                removed.push(model);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    // Removes leftover archives, partial downloads and incompletely unpacked models.
+    //
+    // Only the files of the registry models and the models that were downloaded to the
+    // cache are removed, so the cache directory may be shared with other data. Models that
+    // are being downloaded by another process are cleaned once it is done with them.
+    // Returns the removed paths.
+    pub fn clean(&self) -> Result<Vec<path::PathBuf>, error::Error> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        // Archives were downloaded next to the models without a lock by older versions.
+        let mut legacy_archives = Vec::new();
+        for model in registry::models() {
+            let archive_file_name = model.archive_file_name()?;
+            legacy_archives.push(format!("{}.part", archive_file_name));
+            legacy_archives.push(archive_file_name);
+        }
+
+        let mut removed = Vec::new();
+        for file_name in legacy_archives {
+            let leftover = self.dir.join(file_name);
+            if leftover.is_file() {
+                info!("Removing {}...", leftover.display());
+                remove_path(&leftover)?;
+                removed.push(leftover);
+            }
+        }
+
+        for id in self.known_ids()? {
+            // Avoids creating lock files for the registry models that were never downloaded.
+            if self.model_leftovers(&id)?.is_empty() {
+                continue;
+            }
+            let _lock = lock::FileLock::acquire(&self.lock_path(&id))?;
+
+            for leftover in self.model_leftovers(&id)? {
+                info!("Removing {}...", leftover.display());
+                remove_path(&leftover)?;
+                removed.push(leftover);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    // Returns the leftovers of the model and its directory if it was not completely unpacked.
+    fn model_leftovers(&self, model_id: &str) -> Result<Vec<path::PathBuf>, error::Error> {
+        let mut leftovers = self.leftovers(model_id)?;
+        let dir = self.dir.join(model_id);
+        if dir.is_dir() && !dir.join(COMPLETE_MARKER).exists() {
+            leftovers.push(dir);
+        }
+
+        Ok(leftovers)
+    }
+
+    // Returns the IDs of the registry models and of the models with lock files, which are
+    // created for every model downloaded to the cache.
+    fn known_ids(&self) -> Result<Vec<String>, error::Error> {
+        let mut ids: Vec<String> = registry::models()
+            .into_iter()
+            .map(|model| model.id)
+            .collect();
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            if file_name.starts_with('.')
+                && file_name.ends_with(".lock")
+                && file_name.len() > ".lock".len() + 1
+            {
+                ids.push(file_name[1..file_name.len() - ".lock".len()].to_string());
+            }
+        }
+        ids.retain(|id| check_model_id(id).is_ok());
+        ids.sort();
+        ids.dedup();
+
+        Ok(ids)
+    }
+
+    pub(crate) fn lock_path(&self, model_id: &str) -> path::PathBuf {
+        self.dir.join(format!(".{}.lock", model_id))
+    }
+
+    // Returns the downloaded archives and temporary directories of the model.
+    fn leftovers(&self, model_id: &str) -> Result<Vec<path::PathBuf>, error::Error> {
+        let prefix = format!(".{}.", model_id);
+        let mut file_names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            if file_name.starts_with(&prefix) {
+                file_names.push(file_name);
+            }
+        }
+
+        // Files of the models with IDs like `{model_id}.suffix` share the prefix, but they
+        // are recognized by their own lock files.
+        let other_prefixes: Vec<String> = file_names
+            .iter()
+            .filter(|file_name| file_name.ends_with(".lock") && file_name.len() > prefix.len() + 4)
+            .map(|file_name| format!("{}.", &file_name[..file_name.len() - 5]))
+            .collect();
+
+        Ok(file_names
+            .iter()
+            .filter(|file_name| !file_name.ends_with(".lock"))
+            .filter(|file_name| {
+                !other_prefixes
+                    .iter()
+                    .any(|other_prefix| file_name.starts_with(other_prefix))
+            })
+            .map(|file_name| self.dir.join(file_name))
+            .collect())
+    }
+}
+
+// Marks the model as used now, so it is evicted last.
+pub(crate) fn touch(marker: &path::Path) {
+    if let Err(err) = filetime::set_file_mtime(marker, filetime::FileTime::now()) {
+        debug!("Failed to update {}: {}", marker.display(), err);
+    }
+}
+
+fn cached_model(id: String, dir: path::PathBuf) -> Result<CachedModel, error::Error> {
+    let marker = dir.join(COMPLETE_MARKER);
+    let complete = marker.is_file();
+    let version = if complete {
+        Some(fs::read_to_string(&marker)?.trim().to_string()).filter(|version| !version.is_empty())
+    } else {
+        None
+    };
+    let last_used = fs::metadata(if complete { &marker } else { &dir })?.modified()?;
+
+    Ok(CachedModel {
+        id,
+        size: dir_size(&dir)?,
+        path: dir,
+        version,
+        last_used,
+        complete,
+    })
+}
+
+fn dir_size(dir: &path::Path) -> Result<u64, error::Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+fn remove_path(file_path: &path::Path) -> Result<(), error::Error> {
+    if file_path.is_dir() {
+        fs::remove_dir_all(file_path)?;
+    } else if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
+
+    Ok(())
+}
+
+// Model IDs are used as directory names, so they must not point outside of the cache.
+pub(crate) fn check_model_id(model_id: &str) -> Result<(), error::Error> {
+    if model_id.is_empty()
+        || model_id.starts_with('.')
+        || model_id.contains(|c| c == '/' || c == '\\')
+    {
+        return Err(error::Error::IoError(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid model ID \"{}\"", model_id),
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_model(base_dir: &path::Path, id: &str, size: usize, last_used: i64) {
+        let dir = base_dir.join(id);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("model.pb"), vec![0; size]).unwrap();
+        fs::write(dir.join(COMPLETE_MARKER), format!("{}.tar.gz", id)).unwrap();
+        filetime::set_file_mtime(
+            dir.join(COMPLETE_MARKER),
+            filetime::FileTime::from_unix_time(last_used, 0),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn list_and_prune() {
+        let base_dir = std::env::temp_dir().join("image_ssd_list_and_prune");
+        let _ = fs::remove_dir_all(&base_dir);
+        let config = CacheConfig::new(&base_dir);
+        create_model(&base_dir, "a", 100, 3000);
+        create_model(&base_dir, "b", 100, 1000);
+        create_model(&base_dir, "c", 100, 2000);
+
+        let models = config.list().unwrap();
+        let ids: Vec<&str> = models.iter().map(|model| model.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(models[0].version, Some("a.tar.gz".to_string()));
+        assert!(models[0].size >= 100);
+
+        let removed = config.prune(250).unwrap();
+        let removed_ids: Vec<&str> = removed.iter().map(|model| model.id.as_str()).collect();
+        assert_eq!(removed_ids, vec!["b"]);
+        assert!(!base_dir.join("b").exists());
+        assert!(!config.remove("b").unwrap());
+        assert!(config.remove("c").unwrap());
+        assert!(config.remove("../c").is_err());
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn clean_leftovers() {
+        let base_dir = std::env::temp_dir().join("image_ssd_clean_leftovers");
+        let _ = fs::remove_dir_all(&base_dir);
+        let config = CacheConfig::new(&base_dir);
+        let legacy_archive = registry::find_model(registry::SSD_MOBILENET_V2_COCO)
+            .unwrap()
+            .archive_file_name()
+            .unwrap();
+        create_model(&base_dir, "a", 10, 1000);
+        fs::create_dir_all(base_dir.join("b")).unwrap();
+        fs::create_dir_all(base_dir.join(registry::SSD_MOBILENET_V1_COCO)).unwrap();
+        fs::create_dir_all(base_dir.join(".a.tmp-1")).unwrap();
+        fs::write(base_dir.join(".a.lock"), b"").unwrap();
+        fs::write(base_dir.join(".b.lock"), b"").unwrap();
+        fs::write(base_dir.join(".lock"), b"").unwrap();
+        fs::write(base_dir.join(".a.a.tar.gz.part"), b"").unwrap();
+        fs::write(base_dir.join(&legacy_archive), b"").unwrap();
+
+        let mut removed = config.clean().unwrap();
+        removed.sort();
+        let mut expected = vec![
+            base_dir.join(".a.a.tar.gz.part"),
+            base_dir.join(".a.tmp-1"),
+            base_dir.join("b"),
+            base_dir.join(registry::SSD_MOBILENET_V1_COCO),
+            base_dir.join(legacy_archive),
+        ];
+        expected.sort();
+        assert_eq!(removed, expected);
+        assert!(base_dir.join(".lock").exists());
+        assert!(!base_dir
+            .join(format!(".{}.lock", registry::SSD_MOBILENET_V2_COCO))
+            .exists());
+        assert!(config
+            .get_model(&registry::ModelDescriptor::new(
+                "a",
+                "http://localhost/a.tar.gz",
+                "model.pb"
+            ))
+            .is_ok());
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn keep_unrelated_files() {
+        let base_dir = std::env::temp_dir().join("image_ssd_keep_unrelated_files");
+        let _ = fs::remove_dir_all(&base_dir);
+        let config = CacheConfig::new(&base_dir);
+        create_model(&base_dir, "a", 10, 1000);
+        fs::create_dir_all(base_dir.join("photos")).unwrap();
+        fs::write(base_dir.join("notes.txt"), b"").unwrap();
+        fs::write(base_dir.join("old.tar.gz"), b"").unwrap();
+
+        assert!(config.clean().unwrap().is_empty());
+        assert!(config.prune(0).unwrap().iter().all(|model| model.id == "a"));
+        assert!(base_dir.join("photos").is_dir());
+        assert!(base_dir.join("notes.txt").is_file());
+        assert!(base_dir.join("old.tar.gz").is_file());
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
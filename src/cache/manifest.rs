@@ -0,0 +1,211 @@
+use super::download;
+use super::extract;
+use crate::error;
+use sha2::Digest;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+// The file listing the hashes of all files of an unpacked model.
+pub const MANIFEST_FILE: &str = "MANIFEST.sha256";
+
+// Lists the SHA-256 hashes of model files in the `sha256sum` format.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Manifest {
+    // Paths relative to the model directory with their hashes, sorted by path.
+    pub files: Vec<(String, String)>,
+}
+
+impl Manifest {
+    // Hashes every file in `dir`, except for the cache bookkeeping files starting with a dot,
+    // the manifest itself and its signature.
+    pub fn create(dir: &path::Path) -> Result<Self, error::Error> {
+        let mut files = Vec::new();
+        for relative_path in list_files(dir, path::Path::new(""))? {
+            let hash = sha256_file(&dir.join(&relative_path))?;
+            files.push((to_manifest_path(&relative_path), hash));
+        }
+        files.sort();
+
+        Ok(Manifest { files })
+    }
+
+    pub fn parse(raw_data: &str) -> Result<Self, error::Error> {
+        let mut files = Vec::new();
+        for line in raw_data.lines().filter(|line| !line.trim().is_empty()) {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            match (parts.next(), parts.next()) {
+                (Some(hash), Some(file_path)) if hash.len() == 64 => files.push((
+                    file_path.trim_start().trim_start_matches('*').to_string(),
+                    hash.to_lowercase(),
+                )),
+                _ => return Err(format!("Invalid manifest line {:?}", line).into()),
+            }
+        }
+        files.sort();
+
+        Ok(Manifest { files })
+    }
+
+    pub fn load(dir: &path::Path) -> Result<Self, error::Error> {
+        Manifest::parse(&fs::read_to_string(dir.join(MANIFEST_FILE))?)
+    }
+
+    pub fn save(&self, dir: &path::Path) -> Result<(), error::Error> {
+        fs::write(dir.join(MANIFEST_FILE), self.to_string())?;
+
+        Ok(())
+    }
+
+    // Checks that every listed file in `dir` has the listed hash.
+    pub fn verify(&self, dir: &path::Path) -> Result<(), error::Error> {
+        for (file_path, expected) in self.files.iter() {
+            verify_file(dir, file_path, expected)?;
+        }
+
+        Ok(())
+    }
+
+    // Checks that every one of `files` is listed and has the listed hash. Other listed files
+    // are ignored, so a manifest of a whole archive can be checked against the unpacked part.
+    pub fn verify_files(&self, dir: &path::Path, files: &[&str]) -> Result<(), error::Error> {
+        for file_path in files {
+            let file_path = extract::normalize(path::Path::new(file_path))?;
+            let expected = self
+                .files
+                .iter()
+                .find(|(listed_path, _)| {
+                    extract::normalize(path::Path::new(listed_path))
+                        .ok()
+                        .as_ref()
+                        == Some(&file_path)
+                })
+                .map(|(_, hash)| hash)
+                .ok_or_else(|| {
+                    format!(
+                        "The model file \"{}\" is not listed in the manifest.",
+                        file_path
+                    )
+                })?;
+            verify_file(dir, &file_path, expected)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (file_path, hash) in self.files.iter() {
+            writeln!(f, "{}  {}", hash, file_path)?;
+        }
+        Ok(())
+    }
+}
+
+fn verify_file(dir: &path::Path, file_path: &str, expected: &str) -> Result<(), error::Error> {
+    let full_path = dir.join(file_path);
+    if !is_safe_path(file_path) || !full_path.is_file() {
+        return Err(error::Error::IoError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("The model file \"{}\" is missing.", full_path.display()),
+        )));
+    }
+
+    let actual = sha256_file(&full_path)?;
+    if actual != expected {
+        return Err(error::Error::ChecksumMismatch(error::ChecksumMismatch {
+            source: full_path.display().to_string(),
+            expected: expected.to_string(),
+            actual,
+        }));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn sha256_file(file_path: &path::Path) -> Result<String, error::Error> {
+    let mut hasher = sha2::Sha256::new();
+    download::hash_file(file_path, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn list_files(
+    dir: &path::Path,
+    relative_dir: &path::Path,
+) -> Result<Vec<path::PathBuf>, error::Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir.join(relative_dir))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        // The manifest and its signature are not model files.
+        let name = file_name.to_string_lossy();
+        if name.starts_with('.') || name.starts_with(MANIFEST_FILE) {
+            continue;
+        }
+
+        let relative_path = relative_dir.join(&file_name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            files.extend(list_files(dir, &relative_path)?);
+        } else if file_type.is_file() {
+            files.push(relative_path);
+        }
+    }
+
+    Ok(files)
+}
+
+fn to_manifest_path(relative_path: &path::Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Manifests may come from outside, so their paths must stay inside the model directory.
+fn is_safe_path(file_path: &str) -> bool {
+    path::Path::new(file_path)
+        .components()
+        .all(|component| matches!(component, path::Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_verify() {
+        let dir = std::env::temp_dir().join("image_ssd_manifest_create_and_verify");
+        fs::create_dir_all(dir.join("model")).unwrap();
+        fs::write(dir.join("model/graph.pb"), b"abc").unwrap();
+        fs::write(dir.join(".complete"), b"").unwrap();
+
+        let manifest = Manifest::create(&dir).unwrap();
+        assert_eq!(
+            manifest.to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  model/graph.pb\n"
+        );
+        assert_eq!(Manifest::parse(&manifest.to_string()).unwrap(), manifest);
+        assert!(manifest.verify(&dir).is_ok());
+
+        fs::write(dir.join("model/graph.pb"), b"abd").unwrap();
+        match manifest.verify(&dir) {
+            Err(error::Error::ChecksumMismatch(_)) => (),
+            _ => panic!("Expected a checksum mismatch"),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_unsafe_paths() {
+        let manifest = Manifest {
+            files: vec![("../graph.pb".to_string(), "0".repeat(64))],
+        };
+        assert!(manifest.verify(&std::env::temp_dir()).is_err());
+    }
+}
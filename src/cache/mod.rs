@@ -0,0 +1,90 @@
+pub mod config;
+pub mod download;
+pub mod extract;
+mod lock;
+pub mod manage;
+pub mod manifest;
+pub mod registry;
+pub mod signature;
+pub mod source;
+
+pub use config::*;
+pub use download::DownloadOptions;
+pub use extract::{ArchiveFormat, ExtractLimits};
+pub use manage::CachedModel;
+pub use manifest::Manifest;
+pub use registry::*;
+pub use signature::{PublicKey, Signature};
+pub use source::{FileSource, HttpSource, MemorySource, ModelSource};
+
+use crate::error;
+use crate::ms_coco;
+use std::path;
+
+// Returns a path to the model graph file located in the cache.
+//
+// The models cache is located inside of the `~/.cache/image_ssd/models` directory unless it is
+// overridden by the environment, see `CacheConfig::from_env`.
+pub fn get_model(model: &ModelDescriptor) -> Result<path::PathBuf, error::Error> {
+    CacheConfig::from_env()?.get_model(model)
+}
+
+// Returns a path to the model graph file and download it if a file not exist.
+pub fn get_or_load_model(model: &ModelDescriptor) -> Result<path::PathBuf, error::Error> {
+    CacheConfig::from_env()?.get_or_load_model(model)
+}
+
+// Returns a path to the graph file of a known model located in the cache.
+pub fn get(model_id: &str) -> Result<path::PathBuf, error::Error> {
+    CacheConfig::from_env()?.get(model_id)
+}
+
+// Returns a path to the graph file of a known model and download it if a file not exist.
+pub fn get_or_load(model_id: &str) -> Result<path::PathBuf, error::Error> {
+    CacheConfig::from_env()?.get_or_load(model_id)
+}
+
+// Checks the cached model files against the manifest written when the model was unpacked.
+pub fn verify_model(model: &ModelDescriptor) -> Result<(), error::Error> {
+    CacheConfig::from_env()?.verify_model(model)
+}
+
+// Checks the files of a known model located in the cache.
+pub fn verify(model_id: &str) -> Result<(), error::Error> {
+    CacheConfig::from_env()?.verify(model_id)
+}
+
+// Returns the label map of the model.
+pub fn get_label_map(model: &ModelDescriptor) -> Result<ms_coco::LabelMap, error::Error> {
+    CacheConfig::from_env()?.get_label_map(model)
+}
+
+// Returns the models located in the cache.
+pub fn list_models() -> Result<Vec<CachedModel>, error::Error> {
+    CacheConfig::from_env()?.list()
+}
+
+// Removes the model from the cache. Returns `false` if the model was not cached.
+pub fn remove_model(model_id: &str) -> Result<bool, error::Error> {
+    CacheConfig::from_env()?.remove(model_id)
+}
+
+// Removes the least recently used models until the cache takes at most `max_size` bytes.
+pub fn prune_models(max_size: u64) -> Result<Vec<CachedModel>, error::Error> {
+    CacheConfig::from_env()?.prune(max_size)
+}
+
+// Removes leftover archives, partial downloads and incompletely unpacked models.
+pub fn clean_cache() -> Result<Vec<path::PathBuf>, error::Error> {
+    CacheConfig::from_env()?.clean()
+}
+
+// Returns a path to the `frozen_inference_graph.pb` file located in the cache.
+pub fn get_ssd_mobilenet_v2_graph() -> Result<path::PathBuf, error::Error> {
+    get(SSD_MOBILENET_V2_COCO)
+}
+
+// Returns a path to the `frozen_inference_graph.pb` file and download it if a file not exist.
+pub fn get_or_load_ssd_mobilenet_v2_graph() -> Result<path::PathBuf, error::Error> {
+    get_or_load(SSD_MOBILENET_V2_COCO)
+}
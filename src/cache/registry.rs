@@ -0,0 +1,268 @@
+use super::extract;
+use super::signature;
+use crate::error;
+
+const TF_MODELS_URL: &str = "https://download.tensorflow.org/models/object_detection";
+const TF_MODELS_MIRROR_URL: &str =
+    "https://storage.googleapis.com/download.tensorflow.org/models/object_detection";
+
+pub const SSD_MOBILENET_V2_COCO: &str = "ssd_mobilenet_v2_coco";
+pub const SSD_MOBILENET_V1_COCO: &str = "ssd_mobilenet_v1_coco";
+pub const SSDLITE_MOBILENET_V2_COCO: &str = "ssdlite_mobilenet_v2_coco";
+pub const SSD_MOBILENET_V1_FPN_COCO: &str = "ssd_mobilenet_v1_fpn_coco";
+
+// Describes where a model is downloaded from and how it is laid out inside its archive.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ModelDescriptor {
+    pub id: String,
+    pub name: String,
+    pub urls: Vec<String>,
+    pub sha256: Option<String>,
+    // The path of the frozen graph inside the archive.
+    pub graph_path: String,
+    // The path of a `.pbtxt` label map inside the archive. The MS COCO label map is used
+    // when it is not set.
+    pub label_map: Option<String>,
+    // The format of the downloaded file. It is detected by the file extension when not set.
+    pub format: Option<extract::ArchiveFormat>,
+    // The URL of a manifest listing the SHA-256 hashes of the archive files, see `Manifest`.
+    pub manifest_url: Option<String>,
+    // The URL of the detached minisign signature of the manifest.
+    pub signature_url: Option<String>,
+}
+
+impl ModelDescriptor {
+    pub fn new(id: &str, url: &str, graph_path: &str) -> Self {
+        ModelDescriptor {
+            id: id.to_string(),
+            name: id.to_string(),
+            urls: vec![url.to_string()],
+            sha256: None,
+            graph_path: graph_path.to_string(),
+            label_map: None,
+            format: None,
+            manifest_url: None,
+            signature_url: None,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    // Adds a mirror URL. Mirrors are tried in the order they were added.
+    pub fn mirror(mut self, url: &str) -> Self {
+        self.urls.push(url.to_string());
+        self
+    }
+
+    pub fn sha256(mut self, sha256: &str) -> Self {
+        self.sha256 = Some(sha256.to_lowercase());
+        self
+    }
+
+    pub fn label_map(mut self, label_map: &str) -> Self {
+        self.label_map = Some(label_map.to_string());
+        self
+    }
+
+    pub fn format(mut self, format: extract::ArchiveFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    // Sets the manifest signed by the model publisher. Its signature is expected next to it
+    // with the `.minisig` extension unless it is set by `signature`.
+    pub fn signed_manifest(mut self, manifest_url: &str) -> Self {
+        self.manifest_url = Some(manifest_url.to_string());
+        self.signature_url = Some(format!(
+            "{}.{}",
+            manifest_url,
+            signature::SIGNATURE_EXTENSION
+        ));
+        self
+    }
+
+    pub fn signature(mut self, signature_url: &str) -> Self {
+        self.signature_url = Some(signature_url.to_string());
+        self
+    }
+
+    // Returns the paths of the files inside the archive the model needs.
+    pub fn files(&self) -> Vec<&str> {
+        let mut files = vec![self.graph_path.as_str()];
+        if let Some(ref label_map) = self.label_map {
+            files.push(label_map);
+        }
+        files
+    }
+
+    // Returns the file name of the downloaded archive.
+    pub fn archive_file_name(&self) -> Result<String, error::Error> {
+        let url = self
+            .urls
+            .first()
+            .ok_or_else(|| format!("The model \"{}\" has no URLs", self.id))?;
+        let file_name = extract::strip_url_query(url)
+            .rsplit(|c| c == '/' || c == '\\')
+            .next()
+            .filter(|file_name| !file_name.is_empty())
+            .ok_or_else(|| "Failed to parse file name")?;
+
+        Ok(file_name.to_string())
+    }
+
+    // Returns the format of the downloaded file.
+    pub fn archive_format(&self) -> Result<extract::ArchiveFormat, error::Error> {
+        match self.format {
+            Some(format) => Ok(format),
+            None => Ok(extract::ArchiveFormat::from_file_name(
+                &self.archive_file_name()?,
+            )),
+        }
+    }
+}
+
+fn tf_zoo_model(id: &str, name: &str, archive_stem: &str) -> ModelDescriptor {
+    ModelDescriptor::new(
+        id,
+        &format!("{}/{}.tar.gz", TF_MODELS_URL, archive_stem),
+        &format!("{}/frozen_inference_graph.pb", archive_stem),
+    )
+    .mirror(&format!("{}/{}.tar.gz", TF_MODELS_MIRROR_URL, archive_stem))
+    .name(name)
+}
+
+// Returns the descriptors of the models from the TensorFlow detection model zoo.
+//
+// Models without a checksum are only downloaded when `CacheConfig::allow_unverified` is set.
+pub fn models() -> Vec<ModelDescriptor> {
+    vec![
+        tf_zoo_model(
+            SSD_MOBILENET_V2_COCO,
+            "SSD MobileNet v2 COCO",
+            "ssd_mobilenet_v2_coco_2018_03_29",
+        )
+        .sha256("b9380178b2e35333f1a735e39745928488bdabeb9ed20bc6fa07af8172cb5adc"),
+        // XXX: the checksums of the v1, SSDLite and FPN archives are not pinned yet, so they are
+        // only downloaded with `CacheConfig::allow_unverified`.
+        tf_zoo_model(
+            SSD_MOBILENET_V1_COCO,
+            "SSD MobileNet v1 COCO",
+            "ssd_mobilenet_v1_coco_2018_01_28",
+        ),
+        tf_zoo_model(
+            SSDLITE_MOBILENET_V2_COCO,
+            "SSDLite MobileNet v2 COCO",
+            "ssdlite_mobilenet_v2_coco_2018_05_09",
+        ),
+        tf_zoo_model(
+            SSD_MOBILENET_V1_FPN_COCO,
+            "SSD MobileNet v1 FPN COCO",
+            "ssd_mobilenet_v1_fpn_shared_box_predictor_640x640_coco14_sync_2018_07_03",
+        ),
+    ]
+}
+
+// Returns the descriptor of a known model.
+pub fn find_model(model_id: &str) -> Result<ModelDescriptor, error::Error> {
+    models()
+        .into_iter()
+        .find(|model| model.id == model_id)
+        .ok_or_else(|| format!("Unknown model \"{}\"", model_id).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::download;
+    use super::super::source;
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn find_known_model() {
+        let model = find_model(SSD_MOBILENET_V2_COCO).unwrap();
+        assert_eq!(
+            model.archive_file_name().unwrap(),
+            "ssd_mobilenet_v2_coco_2018_03_29.tar.gz"
+        );
+        assert_eq!(model.urls.len(), 2);
+        assert_eq!(
+            model.graph_path,
+            "ssd_mobilenet_v2_coco_2018_03_29/frozen_inference_graph.pb"
+        );
+    }
+
+    #[test]
+    fn fall_back_to_mirror() {
+        let base_dir = std::env::temp_dir().join("image_ssd_fall_back_to_mirror");
+        let _ = fs::remove_dir_all(&base_dir);
+        fs::create_dir_all(&base_dir).unwrap();
+
+        for model in models() {
+            assert!(model.urls[0].starts_with(TF_MODELS_URL));
+            assert!(model.urls[1].starts_with(TF_MODELS_MIRROR_URL));
+
+            let source = source::MemorySource::new().file(&model.urls[1], b"mirror".to_vec());
+            let options = download::DownloadOptions {
+                retries: 0,
+                ..download::DownloadOptions::default()
+            }
+            .source(source.clone());
+            let archive = base_dir.join(model.archive_file_name().unwrap());
+            download::download(&archive, &model.urls, None, &options).unwrap();
+            assert_eq!(fs::read(&archive).unwrap(), b"mirror");
+
+            // The mirror is only used when the primary URL fails.
+            source.insert(&model.urls[0], b"primary".to_vec());
+            download::download(&archive, &model.urls, None, &options).unwrap();
+            assert_eq!(fs::read(&archive).unwrap(), b"primary");
+        }
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn checksums_are_sha256() {
+        for model in models() {
+            if let Some(ref sha256) = model.sha256 {
+                assert_eq!(sha256.len(), 64, "{}", model.id);
+                assert!(
+                    sha256.chars().all(|c| c.is_ascii_hexdigit()),
+                    "{}",
+                    model.id
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "the v1, SSDLite and FPN archive checksums are not pinned yet"]
+    fn every_model_has_checksum() {
+        for model in models() {
+            assert!(model.sha256.is_some(), "{} has no checksum", model.id);
+        }
+    }
+
+    #[test]
+    fn ignore_url_query() {
+        let model = ModelDescriptor::new(
+            "model",
+            "https://example.com/models/model.zip?token=abc/def#files",
+            "model.pb",
+        );
+        assert_eq!(model.archive_file_name().unwrap(), "model.zip");
+        assert_eq!(model.archive_format().unwrap(), extract::ArchiveFormat::Zip);
+        assert!(
+            ModelDescriptor::new("model", "https://example.com/?model.zip", "model.pb")
+                .archive_file_name()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn find_unknown_model() {
+        assert!(find_model("yolo").is_err());
+    }
+}
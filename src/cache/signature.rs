@@ -0,0 +1,251 @@
+use crate::error;
+use blake2::Digest;
+use ed25519_dalek::Verifier;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path;
+
+// The extension of a detached signature file.
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+const UNTRUSTED_COMMENT: &str = "untrusted comment:";
+const TRUSTED_COMMENT: &str = "trusted comment:";
+
+// An Ed25519 public key in the minisign format.
+#[derive(Clone)]
+pub struct PublicKey {
+    pub key_id: [u8; 8],
+    key: ed25519_dalek::PublicKey,
+}
+
+impl PublicKey {
+    // Parses the content of a minisign public key file or its base64 encoded key line.
+    pub fn parse(raw_data: &str) -> Result<Self, error::Error> {
+        let encoded = raw_data
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_COMMENT))
+            .ok_or_else(|| signature_error("The public key is empty".to_string()))?;
+        let data = decode(encoded, 42)?;
+        if &data[..2] != b"Ed" {
+            return Err(signature_error(
+                "The public key algorithm is not supported".to_string(),
+            ));
+        }
+
+        let key = ed25519_dalek::PublicKey::from_bytes(&data[10..])
+            .map_err(|err| signature_error(format!("Invalid public key: {}", err)))?;
+        Ok(PublicKey {
+            key_id: key_id(&data[2..10]),
+            key,
+        })
+    }
+
+    pub fn from_file(file_path: &path::Path) -> Result<Self, error::Error> {
+        PublicKey::parse(&fs::read_to_string(file_path)?)
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PublicKey {{ key_id: {}}}", hex(&self.key_id))
+    }
+}
+
+// A detached signature in the minisign format.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    // The signature is made over the BLAKE2b-512 hash of the data.
+    pub prehashed: bool,
+    pub key_id: [u8; 8],
+    pub trusted_comment: String,
+    signature: ed25519_dalek::Signature,
+    global_signature: ed25519_dalek::Signature,
+}
+
+impl Signature {
+    // Parses the content of a `.minisig` file.
+    pub fn parse(raw_data: &str) -> Result<Self, error::Error> {
+        let mut lines = raw_data.lines().map(|line| line.trim_end());
+        let mut next_line = |name: &str| {
+            lines
+                .next()
+                .ok_or_else(|| signature_error(format!("The signature has no {}", name)))
+        };
+
+        if !next_line("untrusted comment")?.starts_with(UNTRUSTED_COMMENT) {
+            return Err(signature_error(
+                "The signature must start with an untrusted comment".to_string(),
+            ));
+        }
+        let data = decode(next_line("signature")?, 74)?;
+        let prehashed = match &data[..2] {
+            b"Ed" => false,
+            b"ED" => true,
+            _ => {
+                return Err(signature_error(
+                    "The signature algorithm is not supported".to_string(),
+                ))
+            }
+        };
+        let trusted_comment = next_line("trusted comment")?;
+        if !trusted_comment.starts_with(TRUSTED_COMMENT) {
+            return Err(signature_error(
+                "The signature has no trusted comment".to_string(),
+            ));
+        }
+        let global_signature = decode(next_line("global signature")?, 64)?;
+
+        Ok(Signature {
+            prehashed,
+            key_id: key_id(&data[2..10]),
+            trusted_comment: trusted_comment[TRUSTED_COMMENT.len()..].trim().to_string(),
+            signature: ed25519_signature(&data[10..])?,
+            global_signature: ed25519_signature(&global_signature)?,
+        })
+    }
+
+    pub fn from_file(file_path: &path::Path) -> Result<Self, error::Error> {
+        Signature::parse(&fs::read_to_string(file_path)?)
+    }
+
+    // Checks that the data is signed by one of the trusted keys, including the trusted comment.
+    pub fn verify(&self, data: &[u8], trusted_keys: &[PublicKey]) -> Result<(), error::Error> {
+        let key = trusted_keys
+            .iter()
+            .find(|key| key.key_id == self.key_id)
+            .ok_or_else(|| {
+                signature_error(format!(
+                    "The signature is made by the untrusted key {}",
+                    hex(&self.key_id)
+                ))
+            })?;
+
+        let verified = if self.prehashed {
+            key.key
+                .verify(&blake2::Blake2b::digest(data), &self.signature)
+        } else {
+            key.key.verify(data, &self.signature)
+        };
+        verified.map_err(|_| signature_error("The signature does not match".to_string()))?;
+
+        let mut global_data = self.signature.to_bytes().to_vec();
+        global_data.extend_from_slice(self.trusted_comment.as_bytes());
+        key.key
+            .verify(&global_data, &self.global_signature)
+            .map_err(|_| {
+                signature_error("The trusted comment signature does not match".to_string())
+            })?;
+
+        Ok(())
+    }
+}
+
+// Returns the path of the detached signature of the file.
+pub(crate) fn signature_file_path(file_path: &path::Path) -> path::PathBuf {
+    let mut file_name = file_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(SIGNATURE_EXTENSION);
+    file_path.with_file_name(file_name)
+}
+
+fn decode(encoded: &str, length: usize) -> Result<Vec<u8>, error::Error> {
+    let data = base64::decode(encoded.trim())
+        .map_err(|err| signature_error(format!("Invalid base64 data: {}", err)))?;
+    if data.len() != length {
+        return Err(signature_error(format!(
+            "Expected {} bytes of data, got {}",
+            length,
+            data.len()
+        )));
+    }
+
+    Ok(data)
+}
+
+fn key_id(data: &[u8]) -> [u8; 8] {
+    let mut key_id = [0; 8];
+    key_id.copy_from_slice(data);
+    key_id
+}
+
+fn ed25519_signature(data: &[u8]) -> Result<ed25519_dalek::Signature, error::Error> {
+    ed25519_dalek::Signature::try_from(data)
+        .map_err(|err| signature_error(format!("Invalid signature: {}", err)))
+}
+
+// Minisign prints key IDs as little-endian numbers.
+fn hex(key_id: &[u8; 8]) -> String {
+    key_id
+        .iter()
+        .rev()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+fn signature_error(message: String) -> error::Error {
+    error::Error::SignatureError(error::SignatureError { message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+
+    const KEY_ID: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn keypair() -> ed25519_dalek::Keypair {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        ed25519_dalek::Keypair { secret, public }
+    }
+
+    fn public_key_file(keypair: &ed25519_dalek::Keypair) -> String {
+        let mut data = b"Ed".to_vec();
+        data.extend_from_slice(&KEY_ID);
+        data.extend_from_slice(keypair.public.as_bytes());
+        format!(
+            "untrusted comment: minisign public key\n{}\n",
+            base64::encode(&data)
+        )
+    }
+
+    fn signature_file(keypair: &ed25519_dalek::Keypair, data: &[u8], comment: &str) -> String {
+        let signature = keypair.sign(&blake2::Blake2b::digest(data)).to_bytes();
+        let mut global_data = signature.to_vec();
+        global_data.extend_from_slice(comment.as_bytes());
+        let global_signature = keypair.sign(&global_data).to_bytes();
+
+        let mut signature_data = b"ED".to_vec();
+        signature_data.extend_from_slice(&KEY_ID);
+        signature_data.extend_from_slice(&signature);
+        format!(
+            "untrusted comment: signature\n{}\ntrusted comment: {}\n{}\n",
+            base64::encode(&signature_data),
+            comment,
+            base64::encode(&global_signature[..])
+        )
+    }
+
+    #[test]
+    fn verify_signature() {
+        let keypair = keypair();
+        let public_key = PublicKey::parse(&public_key_file(&keypair)).unwrap();
+        let signature =
+            Signature::parse(&signature_file(&keypair, b"manifest", "model v1")).unwrap();
+
+        assert!(signature.prehashed);
+        assert_eq!(signature.trusted_comment, "model v1");
+        assert!(signature.verify(b"manifest", &[public_key.clone()]).is_ok());
+        assert!(signature
+            .verify(b"tampered", &[public_key.clone()])
+            .is_err());
+        assert!(signature.verify(b"manifest", &[]).is_err());
+
+        // The trusted comment is covered by the global signature.
+        let mut tampered_comment = signature.clone();
+        tampered_comment.trusted_comment = "model v2".to_string();
+        assert!(tampered_comment.verify(b"manifest", &[public_key]).is_err());
+    }
+}
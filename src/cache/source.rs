@@ -0,0 +1,292 @@
+use crate::error;
+use hashbrown::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek};
+use std::path;
+use std::sync::{Arc, RwLock};
+use std::time;
+
+// An opened model file.
+pub struct Fetched {
+    pub reader: Box<dyn Read + Send>,
+    // The size of the whole file if it is known.
+    pub total: Option<u64>,
+    // Whether the data starts at the requested offset. Otherwise it starts at the beginning
+    // of the file.
+    pub resumed: bool,
+}
+
+impl fmt::Debug for Fetched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Fetched {{ total: {:?}, resumed: {}}}",
+            self.total, self.resumed
+        )
+    }
+}
+
+// Opens model files by their URLs.
+//
+// A source is set with `DownloadOptions::source` to download models through something else
+// than plain HTTP, like an S3-compatible client or a proxy with authentication.
+pub trait ModelSource: Send + Sync {
+    // Opens the file at `url`. Sources that can not seek should return the whole file and
+    // clear `resumed` when `offset` is not zero.
+    fn fetch(
+        &self,
+        url: &str,
+        offset: u64,
+        timeout: time::Duration,
+    ) -> Result<Fetched, error::Error>;
+}
+
+// Downloads files over HTTP, resuming them with range requests.
+#[derive(Clone, Debug, Default)]
+pub struct HttpSource {
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpSource {
+    pub fn new() -> Self {
+        HttpSource::default()
+    }
+
+    // Adds a header sent with every request, e.g. `Authorization`.
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.push((key.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl ModelSource for HttpSource {
+    fn fetch(
+        &self,
+        url: &str,
+        offset: u64,
+        timeout: time::Duration,
+    ) -> Result<Fetched, error::Error> {
+        let mut request = minreq::get(url).with_timeout(timeout.as_secs().max(1));
+        for (key, value) in self.headers.iter() {
+            request = request.with_header(key.as_str(), value.as_str());
+        }
+        if offset > 0 {
+            request = request.with_header("Range", format!("bytes={}-", offset));
+        }
+        let response = request.send_lazy()?;
+
+        let content_length = response
+            .headers
+            .get("content-length")
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        let (total, resumed) = match response.status_code {
+            206 => (content_length.map(|length| length + offset), true),
+            // The server ignored the range request, so the download starts over.
+            200 => (content_length, false),
+            // The requested range starts at the end of the file, which is already complete.
+            416 if offset > 0 => {
+                return Ok(Fetched {
+                    reader: Box::new(io::empty()),
+                    total: Some(offset),
+                    resumed: true,
+                })
+            }
+            status_code => {
+                return Err(format!("HTTP {} {}", status_code, response.reason_phrase).into());
+            }
+        };
+
+        Ok(Fetched {
+            reader: Box::new(ResponseReader { response }),
+            total,
+            resumed,
+        })
+    }
+}
+
+// Reads files from the filesystem by `file://` URLs or plain paths.
+#[derive(Clone, Debug, Default)]
+pub struct FileSource;
+
+impl ModelSource for FileSource {
+    fn fetch(
+        &self,
+        url: &str,
+        offset: u64,
+        _timeout: time::Duration,
+    ) -> Result<Fetched, error::Error> {
+        let file_path = local_path(url).ok_or_else(|| format!("\"{}\" is not a file", url))?;
+        let mut file = fs::File::open(&file_path)?;
+        let total = file.metadata()?.len();
+        // A partial file larger than the source is stale, so it is started over.
+        let resumed = offset <= total;
+        file.seek(io::SeekFrom::Start(if resumed { offset } else { 0 }))?;
+
+        Ok(Fetched {
+            reader: Box::new(file),
+            total: Some(total),
+            resumed,
+        })
+    }
+}
+
+// Serves files from memory, e.g. for tests.
+#[derive(Clone, Default)]
+pub struct MemorySource {
+    files: Arc<RwLock<HashMap<String, Arc<Vec<u8>>>>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        MemorySource::default()
+    }
+
+    pub fn insert(&self, url: &str, data: Vec<u8>) {
+        self.files
+            .write()
+            .unwrap()
+            .insert(url.to_string(), Arc::new(data));
+    }
+
+    pub fn file(self, url: &str, data: Vec<u8>) -> Self {
+        self.insert(url, data);
+        self
+    }
+}
+
+impl fmt::Debug for MemorySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let files = self.files.read().unwrap();
+        let mut urls: Vec<&String> = files.keys().collect();
+        urls.sort();
+        write!(f, "MemorySource {{ files: {:?}}}", urls)
+    }
+}
+
+impl ModelSource for MemorySource {
+    fn fetch(
+        &self,
+        url: &str,
+        offset: u64,
+        _timeout: time::Duration,
+    ) -> Result<Fetched, error::Error> {
+        let data = self
+            .files
+            .read()
+            .unwrap()
+            .get(url)
+            .cloned()
+            .ok_or_else(|| {
+                error::Error::IoError(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("\"{}\" not found", url),
+                ))
+            })?;
+        let total = data.len() as u64;
+        let resumed = offset <= total;
+        let mut reader = io::Cursor::new(SharedBytes(data));
+        reader.set_position(if resumed { offset } else { 0 });
+
+        Ok(Fetched {
+            reader: Box::new(reader),
+            total: Some(total),
+            resumed,
+        })
+    }
+}
+
+// Fetches `file://` URLs and plain paths from the filesystem and everything else over HTTP.
+pub(crate) fn fetch(
+    source: Option<&Arc<dyn ModelSource>>,
+    url: &str,
+    offset: u64,
+    timeout: time::Duration,
+) -> Result<Fetched, error::Error> {
+    match source {
+        Some(source) => source.fetch(url, offset, timeout),
+        None if local_path(url).is_some() => FileSource.fetch(url, offset, timeout),
+        None => HttpSource::new().fetch(url, offset, timeout),
+    }
+}
+
+// Returns the filesystem path of a `file://` URL or a plain path.
+pub(crate) fn local_path(url: &str) -> Option<path::PathBuf> {
+    if url.starts_with("file://") {
+        Some(path::PathBuf::from(&url["file://".len()..]))
+    } else if url.contains("://") {
+        None
+    } else {
+        Some(path::PathBuf::from(url))
+    }
+}
+
+struct ResponseReader {
+    response: minreq::ResponseLazy,
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match self.response.next() {
+                Some(Ok((byte, _))) => {
+                    buffer[read] = byte;
+                    read += 1;
+                }
+                Some(Err(err)) => {
+                    return Err(io::Error::new(io::ErrorKind::Other, err.to_string()))
+                }
+                None => break,
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(fetched: Fetched) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut reader = fetched.reader;
+        reader.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn fetch_from_memory() {
+        let source = MemorySource::new().file("memory://model.pb", b"graph".to_vec());
+        let timeout = time::Duration::from_secs(1);
+
+        let fetched = source.fetch("memory://model.pb", 2, timeout).unwrap();
+        assert_eq!(fetched.total, Some(5));
+        assert!(fetched.resumed);
+        assert_eq!(read_all(fetched), b"aph");
+        assert!(source.fetch("memory://other.pb", 0, timeout).is_err());
+    }
+
+    #[test]
+    fn resolve_local_paths() {
+        assert_eq!(
+            local_path("file:///models/model.pb"),
+            Some(path::PathBuf::from("/models/model.pb"))
+        );
+        assert_eq!(
+            local_path("models/model.pb"),
+            Some(path::PathBuf::from("models/model.pb"))
+        );
+        assert_eq!(local_path("https://example.com/model.pb"), None);
+    }
+}
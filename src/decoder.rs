@@ -0,0 +1,385 @@
+use crate::detection;
+use crate::error;
+use crate::ms_coco;
+
+// An anchor box in normalized center-size coordinates.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct AnchorBox {
+    pub y_center: f32,
+    pub x_center: f32,
+    pub height: f32,
+    pub width: f32,
+}
+
+// Parameters of the SSD multiple grid anchor generator.
+//
+// The defaults match the `ssd_mobilenet_v2_coco` pipeline config for 300x300 inputs.
+#[derive(PartialEq, Clone, Debug)]
+pub struct AnchorConfig {
+    pub feature_map_sizes: Vec<(usize, usize)>,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub aspect_ratios: Vec<f32>,
+    pub interpolated_scale_aspect_ratio: f32,
+    pub reduce_boxes_in_lowest_layer: bool,
+}
+
+impl Default for AnchorConfig {
+    fn default() -> Self {
+        AnchorConfig {
+            feature_map_sizes: vec![(19, 19), (10, 10), (5, 5), (3, 3), (2, 2), (1, 1)],
+            min_scale: 0.2,
+            max_scale: 0.95,
+            aspect_ratios: vec![1.0, 2.0, 0.5, 3.0, 1.0 / 3.0],
+            interpolated_scale_aspect_ratio: 1.0,
+            reduce_boxes_in_lowest_layer: true,
+        }
+    }
+}
+
+impl AnchorConfig {
+    // Generates the anchors ordered by layer, grid row, grid column and box spec, which is
+    // the order of the box predictor outputs.
+    pub fn generate(&self) -> Vec<AnchorBox> {
+        let num_layers = self.feature_map_sizes.len();
+        let mut scales: Vec<f32> = (0..num_layers)
+            .map(|i| {
+                let step = if num_layers > 1 {
+                    i as f32 / (num_layers - 1) as f32
+                } else {
+                    0.0
+                };
+                self.min_scale + (self.max_scale - self.min_scale) * step
+            })
+            .collect();
+        scales.push(1.0);
+
+        let mut anchors = Vec::new();
+        for (layer, &(grid_height, grid_width)) in self.feature_map_sizes.iter().enumerate() {
+            let (scale, scale_next) = (scales[layer], scales[layer + 1]);
+
+            let mut box_specs = Vec::new();
+            if layer == 0 && self.reduce_boxes_in_lowest_layer {
+                box_specs.extend_from_slice(&[(0.1, 1.0), (scale, 2.0), (scale, 0.5)]);
+            } else {
+                for aspect_ratio in self.aspect_ratios.iter() {
+                    box_specs.push((scale, *aspect_ratio));
+                }
+                if self.interpolated_scale_aspect_ratio > 0.0 {
+                    box_specs.push((
+                        (scale * scale_next).sqrt(),
+                        self.interpolated_scale_aspect_ratio,
+                    ));
+                }
+            }
+
+            for y in 0..grid_height {
+                for x in 0..grid_width {
+                    for (scale, aspect_ratio) in box_specs.iter() {
+                        let ratio_sqrt = aspect_ratio.sqrt();
+                        anchors.push(AnchorBox {
+                            y_center: (y as f32 + 0.5) / grid_height as f32,
+                            x_center: (x as f32 + 0.5) / grid_width as f32,
+                            height: scale / ratio_sqrt,
+                            width: scale * ratio_sqrt,
+                        });
+                    }
+                }
+            }
+        }
+
+        anchors
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ScoreConverter {
+    Identity,
+    Sigmoid,
+    Softmax,
+}
+
+// Decodes raw SSD head outputs into detection boxes.
+//
+// Takes the box encodings (`[num_anchors, 4]` as `ty, tx, th, tw`) and the class logits
+// (`[num_anchors, num_classes]`), decodes the boxes against the anchor grid with the
+// variance scale factors, converts the logits to scores and applies per-class
+// non-maximum suppression.
+pub struct SSDDecoder {
+    anchors: Vec<AnchorBox>,
+    scale_factors: [f32; 4],
+    score_converter: ScoreConverter,
+    has_background: bool,
+    score_threshold: f32,
+    iou_threshold: f32,
+    max_detections: usize,
+    label_map: ms_coco::LabelMap,
+}
+
+impl SSDDecoder {
+    pub fn new() -> Result<Self, error::Error> {
+        Ok(SSDDecoder {
+            anchors: AnchorConfig::default().generate(),
+            scale_factors: [10.0, 10.0, 5.0, 5.0],
+            score_converter: ScoreConverter::Sigmoid,
+            has_background: true,
+            score_threshold: 0.01,
+            iou_threshold: 0.6,
+            max_detections: 100,
+            label_map: ms_coco::LabelMap::load()?,
+        })
+    }
+
+    pub fn anchors(mut self, anchors: Vec<AnchorBox>) -> Self {
+        self.anchors = anchors;
+        self
+    }
+
+    // Sets the `y, x, h, w` variance scale factors of the box coder.
+    pub fn scale_factors(mut self, scale_factors: [f32; 4]) -> Self {
+        self.scale_factors = scale_factors;
+        self
+    }
+
+    pub fn score_converter(mut self, score_converter: ScoreConverter) -> Self {
+        self.score_converter = score_converter;
+        self
+    }
+
+    // Sets whether the first class of the logits is the background class.
+    pub fn has_background(mut self, has_background: bool) -> Self {
+        self.has_background = has_background;
+        self
+    }
+
+    pub fn score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = score_threshold;
+        self
+    }
+
+    pub fn iou_threshold(mut self, iou_threshold: f32) -> Self {
+        self.iou_threshold = iou_threshold;
+        self
+    }
+
+    pub fn max_detections(mut self, max_detections: usize) -> Self {
+        self.max_detections = max_detections;
+        self
+    }
+
+    pub fn label_map(mut self, label_map: ms_coco::LabelMap) -> Self {
+        self.label_map = label_map;
+        self
+    }
+
+    pub fn decode(
+        &self,
+        box_encodings: &[f32],
+        class_predictions: &[f32],
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let num_anchors = self.anchors.len();
+        if num_anchors == 0 || box_encodings.len() != num_anchors * 4 {
+            return Err(format!(
+                "Expected {} box encodings for {} anchors, got {}",
+                num_anchors * 4,
+                num_anchors,
+                box_encodings.len()
+            )
+            .into());
+        }
+        if class_predictions.len() % num_anchors != 0 {
+            return Err(format!(
+                "The class predictions size {} is not a multiple of {} anchors",
+                class_predictions.len(),
+                num_anchors
+            )
+            .into());
+        }
+
+        let num_classes = class_predictions.len() / num_anchors;
+        let first_class = if self.has_background { 1 } else { 0 };
+        // Label map IDs start from 1, so without a background class the IDs are shifted.
+        let label_id = |class_idx: usize| (class_idx + 1 - first_class) as i32;
+        // Label maps such as MS COCO have gaps in their IDs, the model still predicts scores
+        // for them, so those classes are skipped.
+        let has_label: Vec<bool> = (0..num_classes)
+            .map(|class_idx| {
+                class_idx >= first_class
+                    && self.label_map.get_label_name(&label_id(class_idx)).is_ok()
+            })
+            .collect();
+
+        let boxes: Vec<[f32; 4]> = box_encodings
+            .chunks_exact(4)
+            .zip(self.anchors.iter())
+            .map(|(encoding, anchor)| self.decode_box(encoding, anchor))
+            .collect();
+
+        let mut candidates: Vec<(usize, usize, f32)> = Vec::new();
+        for (anchor_idx, logits) in class_predictions.chunks_exact(num_classes).enumerate() {
+            let scores = self.convert_scores(logits);
+            for class_idx in first_class..num_classes {
+                if has_label[class_idx] && scores[class_idx] >= self.score_threshold {
+                    candidates.push((anchor_idx, class_idx, scores[class_idx]));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut selected: Vec<(usize, usize, f32)> = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= self.max_detections {
+                break;
+            }
+            let (anchor_idx, class_idx, _) = candidate;
+            let suppressed = selected.iter().any(|(other_idx, other_class, _)| {
+                *other_class == class_idx
+                    && iou(&boxes[anchor_idx], &boxes[*other_idx]) > self.iou_threshold
+            });
+            if !suppressed {
+                selected.push(candidate);
+            }
+        }
+
+        let mut selected_boxes = Vec::with_capacity(selected.len() * 4);
+        let mut selected_classes = Vec::with_capacity(selected.len());
+        let mut selected_scores = Vec::with_capacity(selected.len());
+        for (anchor_idx, class_idx, score) in selected {
+            selected_boxes.extend_from_slice(&boxes[anchor_idx]);
+            selected_classes.push(label_id(class_idx) as f32);
+            selected_scores.push(score);
+        }
+
+        detection::detection_boxes(
+            &self.label_map,
+            &selected_boxes,
+            &selected_classes,
+            &selected_scores,
+        )
+    }
+
+    // Returns the `[y1, x1, y2, x2]` box for the encoding, clipped to the image.
+    fn decode_box(&self, encoding: &[f32], anchor: &AnchorBox) -> [f32; 4] {
+        let [sy, sx, sh, sw] = self.scale_factors;
+        let y_center = encoding[0] / sy * anchor.height + anchor.y_center;
+        let x_center = encoding[1] / sx * anchor.width + anchor.x_center;
+        let height = (encoding[2] / sh).exp() * anchor.height;
+        let width = (encoding[3] / sw).exp() * anchor.width;
+
+        [
+            (y_center - height / 2.0).max(0.0).min(1.0),
+            (x_center - width / 2.0).max(0.0).min(1.0),
+            (y_center + height / 2.0).max(0.0).min(1.0),
+            (x_center + width / 2.0).max(0.0).min(1.0),
+        ]
+    }
+
+    fn convert_scores(&self, logits: &[f32]) -> Vec<f32> {
+        match self.score_converter {
+            ScoreConverter::Identity => logits.to_vec(),
+            ScoreConverter::Sigmoid => logits.iter().map(|l| 1.0 / (1.0 + (-l).exp())).collect(),
+            ScoreConverter::Softmax => {
+                let max = logits
+                    .iter()
+                    .cloned()
+                    .fold(std::f32::NEG_INFINITY, f32::max);
+                let exps: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+                let sum: f32 = exps.iter().sum();
+                exps.iter().map(|e| e / sum).collect()
+            }
+        }
+    }
+}
+
+fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let height = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let width = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = height * width;
+    let union = (a[2] - a[0]) * (a[3] - a[1]) + (b[2] - b[0]) * (b[3] - b[1]) - intersection;
+
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(anchors: Vec<AnchorBox>) -> SSDDecoder {
+        SSDDecoder::new()
+            .unwrap()
+            .anchors(anchors)
+            .score_converter(ScoreConverter::Identity)
+            .score_threshold(0.5)
+    }
+
+    fn anchor(y_center: f32, x_center: f32) -> AnchorBox {
+        AnchorBox {
+            y_center,
+            x_center,
+            height: 0.2,
+            width: 0.2,
+        }
+    }
+
+    #[test]
+    fn generate_mobilenet_v2_anchors() {
+        let anchors = AnchorConfig::default().generate();
+        assert_eq!(anchors.len(), 1917);
+        assert_eq!(anchors[0].y_center, 0.5 / 19.0);
+        assert!((anchors[0].height - 0.1).abs() < 1e-6);
+        assert_eq!(anchors[1916].x_center, 0.5);
+    }
+
+    #[test]
+    fn decode_zero_encodings_to_anchors() {
+        // Background, person, bicycle.
+        let predictions = [0.0, 0.9, 0.0];
+        let boxes = decoder(vec![anchor(0.5, 0.5)])
+            .decode(&[0.0; 4], &predictions)
+            .unwrap();
+
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0].label, "person");
+        assert!((boxes[0].x1 - 0.4).abs() < 1e-6);
+        assert!((boxes[0].y2 - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn suppresses_overlapping_boxes_of_same_class() {
+        let anchors = vec![anchor(0.5, 0.5), anchor(0.5, 0.51), anchor(0.5, 0.52)];
+        let predictions = [
+            0.0, 0.9, 0.0, // person
+            0.0, 0.8, 0.0, // overlapping person
+            0.0, 0.0, 0.7, // bicycle
+        ];
+        let boxes = decoder(anchors).decode(&[0.0; 12], &predictions).unwrap();
+
+        let labels: Vec<&str> = boxes.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, vec!["person", "bicycle"]);
+    }
+
+    #[test]
+    fn skips_classes_missing_in_label_map() {
+        // Background, person and the classes up to the unused MS COCO ID 12.
+        let mut predictions = vec![0.0; 13];
+        predictions[1] = 0.6;
+        predictions[12] = 0.9;
+        let boxes = decoder(vec![anchor(0.5, 0.5)])
+            .decode(&[0.0; 4], &predictions)
+            .unwrap();
+
+        let labels: Vec<&str> = boxes.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, vec!["person"]);
+    }
+
+    #[test]
+    fn rejects_mismatched_outputs() {
+        assert!(decoder(vec![anchor(0.5, 0.5)])
+            .decode(&[0.0; 8], &[0.0; 3])
+            .is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error;
+use crate::ms_coco;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct DetectionBox {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub label: String,
+    pub score: f32,
+}
+
+impl DetectionBox {
+    pub fn width(&self) -> f32 {
+        (self.x2 - self.x1).max(0.0)
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.y2 - self.y1).max(0.0)
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width() * self.height()
+    }
+
+    // Returns the intersection over union of two boxes.
+    pub fn iou(&self, other: &DetectionBox) -> f32 {
+        let width = (self.x2.min(other.x2) - self.x1.max(other.x1)).max(0.0);
+        let height = (self.y2.min(other.y2) - self.y1.max(other.y1)).max(0.0);
+        let intersection = width * height;
+        let union = self.area() + other.area() - intersection;
+
+        if union > 0.0 {
+            intersection / union
+        } else {
+            0.0
+        }
+    }
+}
+
+// Maps the postprocessed SSD outputs to detection boxes.
+//
+// `boxes` holds `[y1, x1, y2, x2]` quadruples and `classes` holds label map IDs.
+pub(crate) fn detection_boxes(
+    label_map: &ms_coco::LabelMap,
+    boxes: &[f32],
+    classes: &[f32],
+    scores: &[f32],
+) -> Result<Vec<DetectionBox>, error::Error> {
+    let label_names: Result<Vec<String>, ms_coco::LabelNotFound> = classes
+        .iter()
+        .map(|class| label_map.get_label_name(&(*class as i32)))
+        .collect();
+    let label_names = label_names?;
+
+    let boxes: Vec<DetectionBox> = zip!(boxes.chunks_exact(4), label_names.iter(), scores.iter())
+        .map(|(bbox, (label, score))| DetectionBox {
+            y1: bbox[0],
+            x1: bbox[1],
+            y2: bbox[2],
+            x2: bbox[3],
+            label: label.to_string(),
+            score: *score,
+        })
+        .collect();
+
+    Ok(boxes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iou() {
+        let a = DetectionBox {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 0.5,
+            y2: 0.5,
+            label: "car".to_string(),
+            score: 1.0,
+        };
+        let b = DetectionBox {
+            x1: 0.25,
+            y1: 0.0,
+            x2: 0.75,
+            y2: 0.5,
+            ..a.clone()
+        };
+
+        assert!((a.iou(&a) - 1.0).abs() < 1e-6);
+        assert!((a.iou(&b) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(
+            a.iou(&DetectionBox {
+                x1: 0.6,
+                x2: 0.9,
+                ..a.clone()
+            }),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use hashbrown::HashMap;
+
+// Distinct colors for the automatic palette.
+const PALETTE: [u32; 20] = [
+    0xE6194B, 0x3CB44B, 0xFFE119, 0x4363D8, 0xF58231, 0x911EB4, 0x46F0F0, 0xF032E6, 0xBCF60C,
+    0xFABEBE, 0x008080, 0xE6BEFF, 0x9A6324, 0xFFFAC8, 0x800000, 0xAAFFC3, 0x808000, 0xFFD8B1,
+    0x000075, 0x808080,
+];
+
+// Chooses the color of a box.
+#[derive(PartialEq, Clone, Debug)]
+pub enum ColorStrategy {
+    // Every box has the border color.
+    Fixed,
+    // Every label has its own color from a palette of distinct colors. Colors are picked by
+    // the label hash, so they are the same between images and runs.
+    Palette,
+    // Labels have the given colors. Other labels have palette colors.
+    Map(HashMap<String, piet::Color>),
+    // The color goes from `low` at the zero score to `high` at the full score.
+    Score { low: piet::Color, high: piet::Color },
+}
+
+impl ColorStrategy {
+    // Builds a label map strategy from `(label, color)` pairs.
+    pub fn map<'a, I>(colors: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, piet::Color)>,
+    {
+        ColorStrategy::Map(
+            colors
+                .into_iter()
+                .map(|(label, color)| (label.to_string(), color))
+                .collect(),
+        )
+    }
+
+    // Returns the color of the box with the label and score.
+    pub fn color(&self, label: &str, score: f32, border_color: &piet::Color) -> piet::Color {
+        match self {
+            ColorStrategy::Fixed => border_color.clone(),
+            ColorStrategy::Palette => palette_color(label),
+            ColorStrategy::Map(colors) => colors
+                .get(label)
+                .cloned()
+                .unwrap_or_else(|| palette_color(label)),
+            ColorStrategy::Score { low, high } => {
+                lerp_color(low, high, score.max(0.0).min(1.0) as f64)
+            }
+        }
+    }
+}
+
+impl Default for ColorStrategy {
+    fn default() -> Self {
+        ColorStrategy::Fixed
+    }
+}
+
+// Returns the palette color of the label.
+pub fn palette_color(label: &str) -> piet::Color {
+    // FNV-1a, because the standard hasher is not guaranteed to be stable between releases.
+    let hash = label.bytes().fold(0x811C_9DC5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    piet::Color::from_rgba32_u32((PALETTE[hash as usize % PALETTE.len()] << 8) | 0xFF)
+}
+
+// Returns black or white, whichever has the higher contrast with the background.
+pub fn contrast_color(background: &piet::Color) -> piet::Color {
+    let (r, g, b, _) = components(background);
+    let luminance = 0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b);
+
+    // The WCAG contrast ratios with white and black text.
+    if 1.05 / (luminance + 0.05) >= (luminance + 0.05) / 0.05 {
+        piet::Color::WHITE
+    } else {
+        piet::Color::BLACK
+    }
+}
+
+// Returns the red, green, blue and alpha channels in the `0.0..=1.0` range.
+pub(crate) fn components(color: &piet::Color) -> (f64, f64, f64, f64) {
+    let rgba = color.as_rgba_u32();
+    let channel = |shift: u32| ((rgba >> shift) & 0xFF) as f64 / 255.0;
+    (channel(24), channel(16), channel(8), channel(0))
+}
+
+fn linear(channel: f64) -> f64 {
+    if channel <= 0.039_28 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lerp_color(from: &piet::Color, to: &piet::Color, t: f64) -> piet::Color {
+    let (r0, g0, b0, a0) = components(from);
+    let (r1, g1, b1, a1) = components(to);
+    piet::Color::rgba(
+        r0 + (r1 - r0) * t,
+        g0 + (g1 - g0) * t,
+        b0 + (b1 - b0) * t,
+        a0 + (a1 - a0) * t,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_colors() {
+        let border_color = piet::Color::BLACK;
+        let red = piet::Color::rgb8(0xFF, 0, 0);
+
+        assert_eq!(
+            ColorStrategy::Fixed.color("car", 0.9, &border_color),
+            border_color
+        );
+        assert_eq!(
+            ColorStrategy::Palette.color("car", 0.9, &border_color),
+            ColorStrategy::Palette.color("car", 0.1, &border_color)
+        );
+        assert_eq!(
+            ColorStrategy::map(vec![("car", red.clone())]).color("car", 0.9, &border_color),
+            red
+        );
+
+        let gradient = ColorStrategy::Score {
+            low: piet::Color::BLACK,
+            high: piet::Color::WHITE,
+        };
+        assert_eq!(
+            gradient.color("car", 0.5, &border_color).as_rgba_u32(),
+            piet::Color::rgb(0.5, 0.5, 0.5).as_rgba_u32()
+        );
+    }
+
+    #[test]
+    fn contrast_text() {
+        assert_eq!(contrast_color(&piet::Color::BLACK), piet::Color::WHITE);
+        assert_eq!(
+            contrast_color(&piet::Color::rgb8(0xFF, 0xE1, 0x19)),
+            piet::Color::BLACK
+        );
+        assert_eq!(
+            contrast_color(&piet::Color::rgb8(0x00, 0x00, 0x75)),
+            piet::Color::WHITE
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use piet::kurbo;
+use std::sync::Arc;
+
+// The values a label text is made of.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Label<'a> {
+    pub label: &'a str,
+    pub score: f32,
+    // The label map ID of the class, known when `ImageBoxes` has a label map.
+    pub class_id: Option<i32>,
+    // The ID of the track, known when tracked boxes are drawn.
+    pub track_id: Option<u64>,
+}
+
+// Returns the label text or `None` to draw no label.
+pub type LabelFormatter = Arc<dyn Fn(&Label) -> Option<String> + Send + Sync>;
+
+// Where the label is drawn relative to its box.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LabelPlacement {
+    // Over the top edge of the box.
+    Above,
+    // In the top-left corner of the box.
+    Inside,
+    // Under the bottom edge of the box.
+    Below,
+    // Above the box, or inside when there is no room above it.
+    Auto,
+}
+
+// Formats the label by the template.
+//
+// `{label}`, `{score}`, `{class_id}` and `{track_id}` are replaced by their values, the score is
+// a percent with one decimal. Unknown IDs are replaced by empty strings. The template is
+// scanned once, so placeholders in the substituted values are kept as they are.
+pub fn format_label(template: &str, label: &Label) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let values = [
+        ("{label}", label.label.to_string()),
+        ("{score}", format!("{:.1}%", label.score * 100.0)),
+        (
+            "{class_id}",
+            optional(label.class_id.map(|id| id.to_string())),
+        ),
+        (
+            "{track_id}",
+            optional(label.track_id.map(|id| id.to_string())),
+        ),
+    ];
+
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, value)) => {
+                text.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+
+    text
+}
+
+// Returns the rectangle of the label with the size placed next to the box and kept within the
+// bounds.
+pub fn place_label(
+    box_rect: kurbo::Rect,
+    size: kurbo::Size,
+    placement: LabelPlacement,
+    bounds: kurbo::Rect,
+) -> kurbo::Rect {
+    let box_rect = box_rect.abs();
+    let y = match placement {
+        LabelPlacement::Above => box_rect.y0 - size.height,
+        LabelPlacement::Inside => box_rect.y0,
+        LabelPlacement::Below => box_rect.y1,
+        LabelPlacement::Auto if box_rect.y0 - size.height >= bounds.y0 => box_rect.y0 - size.height,
+        LabelPlacement::Auto => box_rect.y0,
+    };
+
+    let x = box_rect.x0.min(bounds.x1 - size.width).max(bounds.x0);
+    let y = y.min(bounds.y1 - size.height).max(bounds.y0);
+
+    kurbo::Rect::from_origin_size((x, y), size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_template() {
+        let label = Label {
+            label: "car",
+            score: 0.875,
+            class_id: Some(3),
+            track_id: None,
+        };
+
+        assert_eq!(format_label(" {label} ({score})", &label), " car (87.5%)");
+        assert_eq!(
+            format_label("#{track_id} {label}/{class_id}", &label),
+            "# car/3"
+        );
+        assert_eq!(
+            format_label("{{label}} {unknown}", &label),
+            "{car} {unknown}"
+        );
+
+        // Placeholders in the values are not expanded again.
+        let label = Label {
+            label: "{score} {track_id}",
+            track_id: Some(7),
+            ..label
+        };
+        assert_eq!(
+            format_label("{label}: {track_id}", &label),
+            "{score} {track_id}: 7"
+        );
+    }
+
+    #[test]
+    fn place_labels() {
+        let bounds = kurbo::Rect::new(0.0, 0.0, 100.0, 100.0);
+        let size = kurbo::Size::new(30.0, 10.0);
+        let box_rect = kurbo::Rect::new(20.0, 5.0, 80.0, 50.0);
+
+        assert_eq!(
+            place_label(box_rect, size, LabelPlacement::Below, bounds),
+            kurbo::Rect::new(20.0, 50.0, 50.0, 60.0)
+        );
+        // There is no room above the box, so the label is flipped inside.
+        assert_eq!(
+            place_label(box_rect, size, LabelPlacement::Auto, bounds),
+            kurbo::Rect::new(20.0, 5.0, 50.0, 15.0)
+        );
+        // Labels are kept within the bounds.
+        assert_eq!(
+            place_label(
+                kurbo::Rect::new(90.0, 95.0, 100.0, 100.0),
+                size,
+                LabelPlacement::Below,
+                bounds
+            ),
+            kurbo::Rect::new(70.0, 90.0, 100.0, 100.0)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use piet::kurbo;
+
+// How overlapping labels are handled.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LabelCollisions {
+    // Labels are drawn where they are placed, even on top of each other.
+    Ignore,
+    // Overlapping labels are moved up or down to free space within the bounds and connected
+    // to their places by leader lines.
+    Stack,
+    // Overlapping labels are not drawn.
+    Hide,
+}
+
+// The final position of a label.
+#[derive(PartialEq, Clone, Debug)]
+pub struct PlacedLabel {
+    pub rect: kurbo::Rect,
+    // Connects a moved label to where it was placed.
+    pub leader: Option<kurbo::Line>,
+}
+
+// Moves or hides the labels overlapping each other.
+//
+// `rects` are the placed label rectangles in the order of priority, the first labels keep
+// their places. Returns `None` for hidden labels. A label that does not fit anywhere keeps its
+// place.
+pub fn layout_labels(
+    rects: &[kurbo::Rect],
+    bounds: kurbo::Rect,
+    collisions: LabelCollisions,
+) -> Vec<Option<PlacedLabel>> {
+    let mut placed: Vec<kurbo::Rect> = Vec::with_capacity(rects.len());
+    let mut labels = Vec::with_capacity(rects.len());
+
+    for &rect in rects.iter() {
+        let is_free =
+            |candidate: &kurbo::Rect| !placed.iter().any(|other| overlaps(candidate, other));
+
+        let label = match collisions {
+            LabelCollisions::Ignore => Some(PlacedLabel { rect, leader: None }),
+            LabelCollisions::Hide if is_free(&rect) => Some(PlacedLabel { rect, leader: None }),
+            LabelCollisions::Hide => None,
+            LabelCollisions::Stack => {
+                let free_rect = candidates(rect, bounds).find(|candidate| is_free(candidate));
+                Some(match free_rect {
+                    Some(free_rect) if free_rect != rect => PlacedLabel {
+                        rect: free_rect,
+                        leader: Some(leader(free_rect, rect.origin())),
+                    },
+                    _ => PlacedLabel { rect, leader: None },
+                })
+            }
+        };
+
+        if let Some(ref label) = label {
+            placed.push(label.rect);
+        }
+        labels.push(label);
+    }
+
+    labels
+}
+
+// Returns the rectangle followed by its copies shifted up and down by its height, nearest
+// first, that stay within the bounds.
+fn candidates(rect: kurbo::Rect, bounds: kurbo::Rect) -> impl Iterator<Item = kurbo::Rect> {
+    let height = rect.height();
+    let steps = if height > 0.0 {
+        (bounds.height() / height).ceil() as usize
+    } else {
+        0
+    };
+
+    std::iter::once(rect)
+        .chain((1..=steps).flat_map(move |step| {
+            let offset = kurbo::Vec2::new(0.0, height * step as f64);
+            vec![rect - offset, rect + offset]
+        }))
+        .filter(move |candidate| candidate.y0 >= bounds.y0 && candidate.y1 <= bounds.y1)
+}
+
+fn overlaps(a: &kurbo::Rect, b: &kurbo::Rect) -> bool {
+    let intersection = a.intersect(*b);
+    intersection.width() > 0.0 && intersection.height() > 0.0
+}
+
+// Returns the line from the nearest point of the label to the anchor.
+fn leader(rect: kurbo::Rect, anchor: kurbo::Point) -> kurbo::Line {
+    let start = kurbo::Point::new(
+        anchor.x.max(rect.x0).min(rect.x1),
+        anchor.y.max(rect.y0).min(rect.y1),
+    );
+    kurbo::Line::new(start, anchor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stack_overlapping_labels() {
+        let bounds = kurbo::Rect::new(0.0, 0.0, 100.0, 100.0);
+        let rects = vec![
+            kurbo::Rect::new(10.0, 0.0, 50.0, 10.0),
+            kurbo::Rect::new(20.0, 5.0, 60.0, 15.0),
+            kurbo::Rect::new(70.0, 5.0, 90.0, 15.0),
+        ];
+
+        let labels = layout_labels(&rects, bounds, LabelCollisions::Stack);
+        assert_eq!(
+            labels[0],
+            Some(PlacedLabel {
+                rect: rects[0],
+                leader: None
+            })
+        );
+        let moved = labels[1].clone().unwrap();
+        assert_eq!(moved.rect, kurbo::Rect::new(20.0, 15.0, 60.0, 25.0));
+        assert_eq!(
+            moved.leader,
+            Some(kurbo::Line::new((20.0, 15.0), (20.0, 5.0)))
+        );
+        assert_eq!(
+            labels[2],
+            Some(PlacedLabel {
+                rect: rects[2],
+                leader: None
+            })
+        );
+
+        let labels = layout_labels(&rects, bounds, LabelCollisions::Hide);
+        assert!(labels[1].is_none());
+        assert!(labels[2].is_some());
+    }
+
+    #[test]
+    fn keep_labels_without_room() {
+        let bounds = kurbo::Rect::new(0.0, 0.0, 100.0, 10.0);
+        let rect = kurbo::Rect::new(0.0, 0.0, 50.0, 10.0);
+
+        let labels = layout_labels(&[rect, rect], bounds, LabelCollisions::Stack);
+        assert_eq!(labels[1], Some(PlacedLabel { rect, leader: None }));
+    }
+}
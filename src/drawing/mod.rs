@@ -0,0 +1,448 @@
+extern crate image;
+use crate::detection;
+#[cfg(feature = "piet-common")]
+use crate::error;
+use crate::ms_coco;
+use crate::tracking;
+
+pub mod color;
+pub mod label;
+pub mod layout;
+mod raster;
+pub mod style;
+
+pub use color::*;
+pub use label::*;
+pub use layout::*;
+pub use style::*;
+
+use piet::kurbo;
+
+use image::GenericImageView;
+use piet::{RenderContext, Text, TextLayout, TextLayoutBuilder};
+use std::sync::Arc;
+
+enum LabelText {
+    Template(String),
+    Formatter(LabelFormatter),
+}
+
+pub struct ImageBoxes {
+    // The label text color. It is black or white depending on the box color when not set.
+    label_color: Option<piet::Color>,
+    border_color: piet::Color,
+    border_width: f64,
+    font_name: String,
+    font_size: f64,
+    // The font `draw_rgba` draws the labels with.
+    font_data: Option<rusttype::Font<'static>>,
+    score: f32,
+    style: BoxStyle,
+    colors: ColorStrategy,
+    label_text: LabelText,
+    label_placement: LabelPlacement,
+    label_collisions: LabelCollisions,
+    label_map: Option<ms_coco::LabelMap>,
+}
+
+impl ImageBoxes {
+    pub fn new() -> Self {
+        ImageBoxes {
+            border_color: piet::Color::BLACK,
+            border_width: 2.0,
+            label_color: None,
+            font_name: "Arial".to_string(),
+            font_size: 16.0,
+            font_data: None,
+            score: 0.5,
+            style: BoxStyle::new(),
+            colors: ColorStrategy::Fixed,
+            label_text: LabelText::Template(" {label} ({score})".to_string()),
+            label_placement: LabelPlacement::Inside,
+            label_collisions: LabelCollisions::Ignore,
+            label_map: None,
+        }
+    }
+
+    pub fn font(mut self, font_name: &str, font_size: f64) -> Self {
+        self.font_name = font_name.to_string();
+        self.font_size = font_size;
+        self
+    }
+
+    // Sets the TrueType or OpenType font the labels are drawn with by `draw_rgba`.
+    pub fn font_data(mut self, data: Vec<u8>) -> Self {
+        self.font_data = rusttype::Font::try_from_vec(data);
+        if self.font_data.is_none() {
+            warn!("The font data is invalid, so `draw_rgba` does not draw the labels.");
+        }
+        self
+    }
+
+    pub fn label(mut self, color: piet::Color) -> Self {
+        self.label_color = Some(color);
+        self
+    }
+
+    pub fn border(mut self, color: piet::Color, width: f64) -> Self {
+        self.border_color = color;
+        self.border_width = width;
+        self
+    }
+
+    pub fn score(mut self, score: f32) -> Self {
+        self.score = score;
+        self
+    }
+
+    pub fn style(mut self, style: BoxStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn colors(mut self, colors: ColorStrategy) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    // Sets the label text template, see `format_label`. Use `"{label}"` to hide the score and an
+    // empty template to hide the labels.
+    pub fn label_template(mut self, template: &str) -> Self {
+        self.label_text = LabelText::Template(template.to_string());
+        self
+    }
+
+    // Sets a function making the label text, e.g. a localized class name.
+    pub fn label_formatter<F>(mut self, formatter: F) -> Self
+    where
+        F: Fn(&Label) -> Option<String> + Send + Sync + 'static,
+    {
+        self.label_text = LabelText::Formatter(Arc::new(formatter));
+        self
+    }
+
+    pub fn label_placement(mut self, placement: LabelPlacement) -> Self {
+        self.label_placement = placement;
+        self
+    }
+
+    // Sets how overlapping labels are handled in crowded scenes.
+    pub fn label_collisions(mut self, collisions: LabelCollisions) -> Self {
+        self.label_collisions = collisions;
+        self
+    }
+
+    // Sets the label map the class IDs of the labels are taken from.
+    pub fn label_map(mut self, label_map: ms_coco::LabelMap) -> Self {
+        self.label_map = Some(label_map);
+        self
+    }
+
+    pub fn draw_image<T: piet::RenderContext>(
+        &self,
+        ctx: &mut T,
+        image: &image::DynamicImage,
+    ) -> Result<(), piet::Error> {
+        let width = image.width();
+        let height = image.height();
+        let img_raw = image.to_rgba().to_vec();
+
+        let size = kurbo::Size::new(width as f64, height as f64);
+        let img = ctx.make_image(
+            width as usize,
+            height as usize,
+            &img_raw,
+            piet::ImageFormat::RgbaPremul,
+        )?;
+        ctx.draw_image(
+            &img,
+            size.to_rect(),
+            piet::InterpolationMode::NearestNeighbor,
+        );
+
+        Ok(())
+    }
+
+    pub fn draw_boxes<T: piet::RenderContext>(
+        &self,
+        ctx: &mut T,
+        boxes: Vec<detection::DetectionBox>,
+        width: f64,
+        height: f64,
+    ) -> Result<(), piet::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (b, None)).collect();
+        self.draw_labeled_boxes(ctx, &boxes, width, height)
+    }
+
+    // Draws the tracked boxes, so their labels can show the track IDs.
+    pub fn draw_tracked_boxes<T: piet::RenderContext>(
+        &self,
+        ctx: &mut T,
+        boxes: &[tracking::TrackedBox],
+        width: f64,
+        height: f64,
+    ) -> Result<(), piet::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (&b.detection, Some(b.id))).collect();
+        self.draw_labeled_boxes(ctx, &boxes, width, height)
+    }
+
+    fn draw_labeled_boxes<T: piet::RenderContext>(
+        &self,
+        ctx: &mut T,
+        boxes: &[(&detection::DetectionBox, Option<u64>)],
+        width: f64,
+        height: f64,
+    ) -> Result<(), piet::Error> {
+        // Labels are drawn after all boxes, so boxes never cover them.
+        let mut labels = Vec::new();
+        for (rect, box_color, label_text, score) in self.box_items(boxes, width, height) {
+            self.draw_box(ctx, rect, &box_color);
+            if let Some(label_text) = label_text {
+                labels.push((rect, label_text, box_color, score));
+            }
+        }
+        if labels.is_empty() {
+            return Ok(());
+        }
+
+        let font_family = ctx
+            .text()
+            .font_family(self.font_name.as_str())
+            .ok_or(piet::Error::MissingFont)?;
+        let bounds = kurbo::Rect::new(0.0, 0.0, width, height);
+
+        let mut layouts = Vec::with_capacity(labels.len());
+        let mut sizes = Vec::with_capacity(labels.len());
+        for (rect, label_text, box_color, score) in labels {
+            let text_color = self
+                .label_color
+                .clone()
+                .unwrap_or_else(|| contrast_color(&box_color));
+            let layout = ctx
+                .text()
+                .new_text_layout(label_text.clone())
+                .font(font_family.clone(), self.font_size)
+                .text_color(text_color)
+                .build()?;
+
+            sizes.push((rect, layout.size(), score));
+            layouts.push((layout, label_text, box_color));
+        }
+
+        for (index, placed_label) in self.place_labels(&sizes, bounds) {
+            let (ref layout, ref label_text, ref box_color) = layouts[index];
+            let layout_rect = placed_label.rect;
+            let text_pos = layout_rect.origin().to_vec2();
+
+            if let Some(leader) = placed_label.leader {
+                ctx.stroke(leader, box_color, 1.0);
+            }
+
+            if let Some(ref shadow) = self.style.label_shadow {
+                let shadow_layout = ctx
+                    .text()
+                    .new_text_layout(label_text.clone())
+                    .font(font_family.clone(), self.font_size)
+                    .text_color(shadow.color.clone())
+                    .build()?;
+                ctx.fill(layout_rect + shadow.offset, &shadow.color);
+                ctx.draw_text(&shadow_layout, (text_pos + shadow.offset).to_point());
+            }
+
+            ctx.fill(layout_rect, box_color);
+            ctx.draw_text(layout, text_pos.to_point());
+        }
+
+        Ok(())
+    }
+
+    // Returns the boxes above the score threshold in pixels with their colors and labels.
+    fn box_items(
+        &self,
+        boxes: &[(&detection::DetectionBox, Option<u64>)],
+        width: f64,
+        height: f64,
+    ) -> Vec<(kurbo::Rect, piet::Color, Option<String>, f32)> {
+        boxes
+            .iter()
+            .filter(|(b, _)| b.score > self.score)
+            .map(|(b, track_id)| {
+                let rect = kurbo::Rect::new(
+                    b.x1 as f64 * width,
+                    b.y1 as f64 * height,
+                    b.x2 as f64 * width,
+                    b.y2 as f64 * height,
+                );
+                let box_color = self.colors.color(&b.label, b.score, &self.border_color);
+                (rect, box_color, self.label_text(b, *track_id), b.score)
+            })
+            .collect()
+    }
+
+    // Places the labels of the sizes next to their boxes and resolves their collisions.
+    //
+    // `labels` holds the box rectangles, the label sizes and the scores. Returns the indices of
+    // the drawn labels with their places in the drawing order.
+    fn place_labels(
+        &self,
+        labels: &[(kurbo::Rect, kurbo::Size, f32)],
+        bounds: kurbo::Rect,
+    ) -> Vec<(usize, PlacedLabel)> {
+        let mut order: Vec<usize> = (0..labels.len()).collect();
+        if self.label_collisions != LabelCollisions::Ignore {
+            // The most confident labels keep their places when labels collide.
+            order.sort_by(|&a, &b| {
+                labels[b]
+                    .2
+                    .partial_cmp(&labels[a].2)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+
+        let rects: Vec<kurbo::Rect> = order
+            .iter()
+            .map(|&index| {
+                let (box_rect, size, _) = labels[index];
+                place_label(box_rect, size, self.label_placement, bounds)
+            })
+            .collect();
+
+        order
+            .into_iter()
+            .zip(layout_labels(&rects, bounds, self.label_collisions))
+            .filter_map(|(index, placed_label)| {
+                placed_label.map(|placed_label| (index, placed_label))
+            })
+            .collect()
+    }
+
+    // Returns the label text of the box or `None` if it has no label.
+    fn label_text(&self, b: &detection::DetectionBox, track_id: Option<u64>) -> Option<String> {
+        let label = Label {
+            label: &b.label,
+            score: b.score,
+            class_id: self
+                .label_map
+                .as_ref()
+                .and_then(|label_map| label_map.get_label_id(&b.label)),
+            track_id,
+        };
+
+        match self.label_text {
+            LabelText::Template(ref template) => {
+                Some(format_label(template, &label)).filter(|text| !text.is_empty())
+            }
+            LabelText::Formatter(ref formatter) => formatter(&label),
+        }
+    }
+
+    // Fills and outlines the box according to the style.
+    fn draw_box<T: piet::RenderContext>(
+        &self,
+        ctx: &mut T,
+        rect: kurbo::Rect,
+        color: &piet::Color,
+    ) {
+        let radius = self.style.radius(rect);
+
+        if self.style.fill_opacity > 0.0 {
+            let fill_color = color.clone().with_alpha(self.style.fill_opacity);
+            if radius > 0.0 {
+                ctx.fill(kurbo::RoundedRect::from_rect(rect, radius), &fill_color);
+            } else {
+                ctx.fill(rect, &fill_color);
+            }
+        }
+
+        let mut stroke_style = piet::StrokeStyle::new();
+        if let Some(dashes) = self.style.line.dashes(self.border_width) {
+            stroke_style.set_dash(dashes, 0.0);
+        }
+        if self.style.line == LineStyle::Dotted {
+            stroke_style.set_line_cap(piet::LineCap::Round);
+        }
+
+        if self.style.brackets.is_some() {
+            for line in self.style.bracket_lines(rect) {
+                let mut path = kurbo::BezPath::new();
+                path.move_to(line[0]);
+                path.line_to(line[1]);
+                path.line_to(line[2]);
+                ctx.stroke_styled(path, color, self.border_width, &stroke_style);
+            }
+        } else if radius > 0.0 {
+            ctx.stroke_styled(
+                kurbo::RoundedRect::from_rect(rect, radius),
+                color,
+                self.border_width,
+                &stroke_style,
+            );
+        } else {
+            ctx.stroke_styled(rect, color, self.border_width, &stroke_style);
+        }
+    }
+
+    // Draws the boxes onto a copy of the image with the platform graphics backend. Its width is
+    // rounded up to a multiple of 16, use `draw_rgba` to keep the size.
+    #[cfg(feature = "piet-common")]
+    pub fn draw(
+        &self,
+        input_image: &image::DynamicImage,
+        boxes: Vec<detection::DetectionBox>,
+    ) -> Result<image::DynamicImage, error::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (b, None)).collect();
+        self.render(input_image, &boxes)
+    }
+
+    // Draws the tracked boxes, so their labels can show the track IDs.
+    #[cfg(feature = "piet-common")]
+    pub fn draw_tracked(
+        &self,
+        input_image: &image::DynamicImage,
+        boxes: &[tracking::TrackedBox],
+    ) -> Result<image::DynamicImage, error::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (&b.detection, Some(b.id))).collect();
+        self.render(input_image, &boxes)
+    }
+
+    #[cfg(feature = "piet-common")]
+    fn render(
+        &self,
+        input_image: &image::DynamicImage,
+        boxes: &[(&detection::DetectionBox, Option<u64>)],
+    ) -> Result<image::DynamicImage, error::Error> {
+        let img = input_image.resize_exact(
+            (input_image.width() as f32 / 16.0).ceil() as u32 * 16,
+            input_image.height(),
+            image::imageops::FilterType::Nearest,
+        ); // XXX: non-16-divisible width affect the color accuracy in some way
+
+        let width = img.width();
+        let height = img.height();
+
+        let mut device = piet_common::Device::new()?;
+        let mut bitmap = device.bitmap_target(width as usize, height as usize, 1.0)?;
+        let mut ctx = bitmap.render_context();
+        ctx.clear(piet::Color::WHITE);
+
+        self.draw_image(&mut ctx, &img)?;
+        self.draw_labeled_boxes(&mut ctx, boxes, width as f64, height as f64)?;
+
+        ctx.finish()?;
+        std::mem::drop(ctx);
+
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        bitmap.copy_raw_pixels(piet::ImageFormat::RgbaPremul, &mut buffer)?;
+        let res_img = image::ImageBuffer::from_raw(width, height, buffer)
+            .map(image::DynamicImage::ImageRgba8)
+            .ok_or_else(|| "Cannot contruct a buffer from a generic container")?;
+
+        Ok(res_img)
+    }
+}
+
+impl Default for ImageBoxes {
+    fn default() -> Self {
+        ImageBoxes::new()
+    }
+}
@@ -0,0 +1,454 @@
+use super::color;
+use super::ImageBoxes;
+use crate::detection;
+use crate::error;
+use crate::tracking;
+use piet::kurbo;
+
+// The number of segments a rounded corner is approximated by.
+const CORNER_SEGMENTS: usize = 8;
+
+impl ImageBoxes {
+    // Draws the boxes into the image in place without a graphics backend.
+    //
+    // Unlike `draw`, the image keeps its size. Labels are only drawn when the font is set by
+    // `font_data`, because system fonts are not available without a backend.
+    pub fn draw_rgba(
+        &self,
+        image: &mut image::RgbaImage,
+        boxes: Vec<detection::DetectionBox>,
+    ) -> Result<(), error::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (b, None)).collect();
+        self.render_rgba(image, &boxes)
+    }
+
+    // Draws the tracked boxes into the image in place, see `draw_rgba`.
+    pub fn draw_tracked_rgba(
+        &self,
+        image: &mut image::RgbaImage,
+        boxes: &[tracking::TrackedBox],
+    ) -> Result<(), error::Error> {
+        let boxes: Vec<_> = boxes.iter().map(|b| (&b.detection, Some(b.id))).collect();
+        self.render_rgba(image, &boxes)
+    }
+
+    fn render_rgba(
+        &self,
+        image: &mut image::RgbaImage,
+        boxes: &[(&detection::DetectionBox, Option<u64>)],
+    ) -> Result<(), error::Error> {
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let mut canvas = Canvas::new(image);
+
+        let mut labels = Vec::new();
+        for (rect, box_color, label_text, score) in self.box_items(boxes, width, height) {
+            self.raster_box(&mut canvas, rect, &box_color);
+            if let Some(label_text) = label_text {
+                labels.push((rect, label_text, box_color, score));
+            }
+        }
+        if labels.is_empty() {
+            return Ok(());
+        }
+
+        let font = match self.font_data {
+            Some(ref font) => font,
+            None => {
+                warn!("No font data is set, so the labels are not drawn.");
+                return Ok(());
+            }
+        };
+        let scale = rusttype::Scale::uniform(self.font_size as f32);
+        let bounds = kurbo::Rect::new(0.0, 0.0, width, height);
+
+        let sizes: Vec<_> = labels
+            .iter()
+            .map(|(rect, label_text, _, score)| (*rect, text_size(font, scale, label_text), *score))
+            .collect();
+        for (index, placed_label) in self.place_labels(&sizes, bounds) {
+            let (_, ref label_text, ref box_color, _) = labels[index];
+            let rect = placed_label.rect;
+
+            if let Some(leader) = placed_label.leader {
+                canvas.stroke_polyline(&[leader.p0, leader.p1], 1.0, box_color, None);
+            }
+            if let Some(ref shadow) = self.style.label_shadow {
+                canvas.fill_rect(rect + shadow.offset, &shadow.color);
+                canvas.draw_text(
+                    font,
+                    scale,
+                    rect.origin() + shadow.offset,
+                    label_text,
+                    &shadow.color,
+                );
+            }
+
+            let text_color = self
+                .label_color
+                .clone()
+                .unwrap_or_else(|| color::contrast_color(box_color));
+            canvas.fill_rect(rect, box_color);
+            canvas.draw_text(font, scale, rect.origin(), label_text, &text_color);
+        }
+
+        Ok(())
+    }
+
+    // Fills and outlines the box according to the style, see `draw_box`.
+    fn raster_box(&self, canvas: &mut Canvas, rect: kurbo::Rect, color: &piet::Color) {
+        let radius = self.style.radius(rect);
+
+        if self.style.fill_opacity > 0.0 {
+            let fill_color = color.clone().with_alpha(self.style.fill_opacity);
+            canvas.fill_rounded_rect(rect, radius, &fill_color);
+        }
+
+        let dashes = self.style.line.dashes(self.border_width);
+        if self.style.brackets.is_some() {
+            for line in self.style.bracket_lines(rect) {
+                canvas.stroke_polyline(&line, self.border_width, color, dashes.as_deref());
+            }
+        } else {
+            canvas.stroke_polyline(
+                &outline(rect, radius),
+                self.border_width,
+                color,
+                dashes.as_deref(),
+            );
+        }
+    }
+}
+
+// Alpha-blends shapes into an RGBA image, sampling the pixel centers.
+pub(crate) struct Canvas<'a> {
+    image: &'a mut image::RgbaImage,
+}
+
+impl<'a> Canvas<'a> {
+    pub(crate) fn new(image: &'a mut image::RgbaImage) -> Self {
+        Canvas { image }
+    }
+
+    pub(crate) fn fill_rect(&mut self, rect: kurbo::Rect, color: &piet::Color) {
+        self.fill_rounded_rect(rect, 0.0, color);
+    }
+
+    pub(crate) fn fill_rounded_rect(
+        &mut self,
+        rect: kurbo::Rect,
+        radius: f64,
+        color: &piet::Color,
+    ) {
+        let rect = rect.abs();
+        let inner = kurbo::Rect::new(
+            rect.x0 + radius,
+            rect.y0 + radius,
+            rect.x1 - radius,
+            rect.y1 - radius,
+        );
+        let rgba = color::components(color);
+
+        for (x, y) in pixels(rect, self.image.dimensions()) {
+            let center = pixel_center(x, y);
+            if !rect.contains(center) {
+                continue;
+            }
+            let nearest = kurbo::Point::new(
+                center.x.max(inner.x0).min(inner.x1),
+                center.y.max(inner.y0).min(inner.y1),
+            );
+            if radius <= 0.0 || (center - nearest).hypot() <= radius {
+                self.blend(x, y, rgba);
+            }
+        }
+    }
+
+    // Strokes the polyline with round joins and caps. `dashes` are the lengths of the
+    // alternating dashes and gaps.
+    pub(crate) fn stroke_polyline(
+        &mut self,
+        points: &[kurbo::Point],
+        width: f64,
+        color: &piet::Color,
+        dashes: Option<&[f64]>,
+    ) {
+        let segments = match dashes {
+            Some(dashes) => dash_segments(points, dashes),
+            None => points.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+        };
+        if segments.is_empty() {
+            return;
+        }
+
+        let half_width = width.max(1.0) / 2.0;
+        let bounds = segments
+            .iter()
+            .fold(
+                kurbo::Rect::from_points(segments[0].0, segments[0].0),
+                |bounds, (a, b)| bounds.union_pt(*a).union_pt(*b),
+            )
+            .inflate(half_width, half_width);
+        let (x0, x1, y0, y1) = pixel_range(bounds, self.image.dimensions());
+        let rgba = color::components(color);
+
+        // Every pixel is blended once, even where the segments overlap.
+        let stride = (x1 - x0) as usize;
+        let mut covered = vec![false; stride * (y1 - y0) as usize];
+        for (a, b) in segments {
+            let segment_bounds = kurbo::Rect::from_points(a, b).inflate(half_width, half_width);
+            for (x, y) in pixels(segment_bounds, self.image.dimensions()) {
+                let index = (y - y0) as usize * stride + (x - x0) as usize;
+                if !covered[index] && distance_to_segment(pixel_center(x, y), a, b) <= half_width {
+                    covered[index] = true;
+                    self.blend(x, y, rgba);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn draw_text(
+        &mut self,
+        font: &rusttype::Font,
+        scale: rusttype::Scale,
+        origin: kurbo::Point,
+        text: &str,
+        color: &piet::Color,
+    ) {
+        let (r, g, b, a) = color::components(color);
+        let ascent = font.v_metrics(scale).ascent;
+        let start = rusttype::point(origin.x as f32, origin.y as f32 + ascent);
+
+        for glyph in font.layout(text, scale, start) {
+            if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    let x = bounding_box.min.x as i64 + x as i64;
+                    let y = bounding_box.min.y as i64 + y as i64;
+                    if x >= 0 && y >= 0 {
+                        self.blend(x as u32, y as u32, (r, g, b, a * coverage as f64));
+                    }
+                });
+            }
+        }
+    }
+
+    fn blend(&mut self, x: u32, y: u32, (r, g, b, a): (f64, f64, f64, f64)) {
+        if x >= self.image.width() || y >= self.image.height() || a <= 0.0 {
+            return;
+        }
+
+        let pixel = self.image.get_pixel_mut(x, y);
+        let dst_alpha = pixel[3] as f64 / 255.0;
+        let out_alpha = a + dst_alpha * (1.0 - a);
+        for (channel, src) in [r, g, b].iter().enumerate() {
+            let dst = pixel[channel] as f64 / 255.0;
+            let value = (src * a + dst * dst_alpha * (1.0 - a)) / out_alpha;
+            pixel[channel] = (value * 255.0).round() as u8;
+        }
+        pixel[3] = (out_alpha * 255.0).round() as u8;
+    }
+}
+
+// Returns the size of the text drawn by `Canvas::draw_text`.
+pub(crate) fn text_size(font: &rusttype::Font, scale: rusttype::Scale, text: &str) -> kurbo::Size {
+    let v_metrics = font.v_metrics(scale);
+    let width = font
+        .layout(text, scale, rusttype::point(0.0, 0.0))
+        .last()
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0);
+
+    kurbo::Size::new(width as f64, (v_metrics.ascent - v_metrics.descent) as f64)
+}
+
+// Returns the closed outline of the rectangle with rounded corners.
+fn outline(rect: kurbo::Rect, radius: f64) -> Vec<kurbo::Point> {
+    let rect = rect.abs();
+    if radius <= 0.0 {
+        return vec![
+            kurbo::Point::new(rect.x0, rect.y0),
+            kurbo::Point::new(rect.x1, rect.y0),
+            kurbo::Point::new(rect.x1, rect.y1),
+            kurbo::Point::new(rect.x0, rect.y1),
+            kurbo::Point::new(rect.x0, rect.y0),
+        ];
+    }
+
+    let corners = [
+        (rect.x1 - radius, rect.y0 + radius, -90.0),
+        (rect.x1 - radius, rect.y1 - radius, 0.0),
+        (rect.x0 + radius, rect.y1 - radius, 90.0),
+        (rect.x0 + radius, rect.y0 + radius, 180.0),
+    ];
+    let mut points: Vec<kurbo::Point> = corners
+        .iter()
+        .flat_map(|&(cx, cy, start): &(f64, f64, f64)| {
+            (0..=CORNER_SEGMENTS).map(move |step| {
+                let angle = (start + 90.0 * step as f64 / CORNER_SEGMENTS as f64).to_radians();
+                kurbo::Point::new(cx + radius * angle.cos(), cy + radius * angle.sin())
+            })
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+// Splits the polyline into the dash segments. Zero-length dashes are dots.
+fn dash_segments(points: &[kurbo::Point], dashes: &[f64]) -> Vec<(kurbo::Point, kurbo::Point)> {
+    if dashes.is_empty() || dashes.iter().sum::<f64>() <= 0.0 {
+        return points.windows(2).map(|pair| (pair[0], pair[1])).collect();
+    }
+
+    let mut segments = Vec::new();
+    let mut dash_index = 0;
+    // The length left of the current dash or gap.
+    let mut left = dashes[0];
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let length = (end - start).hypot();
+        let mut position = 0.0;
+
+        while position < length || (left == 0.0 && dash_index % 2 == 0) {
+            let step = left.min(length - position);
+            if dash_index % 2 == 0 {
+                let from = start.lerp(end, if length > 0.0 { position / length } else { 0.0 });
+                let to = start.lerp(
+                    end,
+                    if length > 0.0 {
+                        (position + step) / length
+                    } else {
+                        0.0
+                    },
+                );
+                segments.push((from, to));
+            }
+            position += step;
+            left -= step;
+            if left <= 0.0 {
+                dash_index = (dash_index + 1) % dashes.len();
+                left = dashes[dash_index];
+            } else {
+                break;
+            }
+        }
+    }
+
+    segments
+}
+
+fn distance_to_segment(point: kurbo::Point, a: kurbo::Point, b: kurbo::Point) -> f64 {
+    let ab = b - a;
+    let length_squared = ab.hypot2();
+    let t = if length_squared > 0.0 {
+        ((point - a).dot(ab) / length_squared).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+
+    (point - a.lerp(b, t)).hypot()
+}
+
+// Returns the `(x0, x1, y0, y1)` pixel range of the image of the dimensions under the
+// rectangle.
+fn pixel_range(rect: kurbo::Rect, (width, height): (u32, u32)) -> (u32, u32, u32, u32) {
+    let clamp = |value: f64, max: u32| value.max(0.0).min(max as f64) as u32;
+    (
+        clamp(rect.x0.floor(), width),
+        clamp(rect.x1.ceil(), width),
+        clamp(rect.y0.floor(), height),
+        clamp(rect.y1.ceil(), height),
+    )
+}
+
+// Returns the pixels of the image of the dimensions under the rectangle.
+fn pixels(rect: kurbo::Rect, dimensions: (u32, u32)) -> impl Iterator<Item = (u32, u32)> {
+    let (x0, x1, y0, y1) = pixel_range(rect, dimensions);
+    (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+}
+
+fn pixel_center(x: u32, y: u32) -> kurbo::Point {
+    kurbo::Point::new(x as f64 + 0.5, y as f64 + 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: image::Rgba<u8> = image::Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+    const BLACK: image::Rgba<u8> = image::Rgba([0, 0, 0, 0xFF]);
+
+    #[test]
+    fn draw_boxes_in_place() {
+        let mut image = image::RgbaImage::from_pixel(40, 20, WHITE);
+        let boxes = vec![detection::DetectionBox {
+            x1: 0.25,
+            y1: 0.25,
+            x2: 0.75,
+            y2: 0.75,
+            label: "car".to_string(),
+            score: 0.9,
+        }];
+
+        ImageBoxes::new().draw_rgba(&mut image, boxes).unwrap();
+        assert_eq!(image.dimensions(), (40, 20));
+        assert_eq!(*image.get_pixel(10, 10), BLACK);
+        assert_eq!(*image.get_pixel(20, 5), BLACK);
+        assert_eq!(*image.get_pixel(20, 10), WHITE);
+        assert_eq!(*image.get_pixel(2, 2), WHITE);
+    }
+
+    #[test]
+    fn blend_translucent_fill() {
+        let mut image = image::RgbaImage::from_pixel(4, 4, WHITE);
+        let mut canvas = Canvas::new(&mut image);
+        canvas.fill_rect(
+            kurbo::Rect::new(0.0, 0.0, 2.0, 4.0),
+            &piet::Color::BLACK.with_alpha(0.6),
+        );
+
+        assert_eq!(
+            *image.get_pixel(1, 1),
+            image::Rgba([0x66, 0x66, 0x66, 0xFF])
+        );
+        assert_eq!(*image.get_pixel(2, 1), WHITE);
+    }
+
+    #[test]
+    fn stroke_overlapping_segments_once() {
+        let mut image = image::RgbaImage::from_pixel(20, 20, WHITE);
+        let mut canvas = Canvas::new(&mut image);
+        canvas.stroke_polyline(
+            &[
+                kurbo::Point::new(2.0, 10.0),
+                kurbo::Point::new(18.0, 10.0),
+                kurbo::Point::new(10.0, 10.0),
+                kurbo::Point::new(10.0, 2.0),
+            ],
+            2.0,
+            &piet::Color::BLACK.with_alpha(0.6),
+            None,
+        );
+
+        let gray = image::Rgba([0x66, 0x66, 0x66, 0xFF]);
+        assert_eq!(*image.get_pixel(10, 9), gray);
+        assert_eq!(*image.get_pixel(4, 9), gray);
+        assert_eq!(*image.get_pixel(10, 4), gray);
+        // Only the pixels close to the segments are covered, not their whole bounding box.
+        assert_eq!(*image.get_pixel(4, 4), WHITE);
+        assert_eq!(*image.get_pixel(16, 4), WHITE);
+    }
+
+    #[test]
+    fn split_dashes() {
+        let points = [kurbo::Point::new(0.0, 0.0), kurbo::Point::new(10.0, 0.0)];
+        let segments = dash_segments(&points, &[4.0, 2.0]);
+
+        assert_eq!(
+            segments,
+            vec![
+                (kurbo::Point::new(0.0, 0.0), kurbo::Point::new(4.0, 0.0)),
+                (kurbo::Point::new(6.0, 0.0), kurbo::Point::new(10.0, 0.0)),
+            ]
+        );
+        assert_eq!(dash_segments(&points, &[0.0, 5.0]).len(), 3);
+    }
+}
@@ -0,0 +1,148 @@
+use piet::kurbo;
+
+// The pattern of the box outline.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LineStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl LineStyle {
+    // Returns the lengths of the alternating dashes and gaps for the line width.
+    pub fn dashes(self, width: f64) -> Option<Vec<f64>> {
+        match self {
+            LineStyle::Solid => None,
+            LineStyle::Dashed => Some(vec![width * 4.0, width * 2.0]),
+            // Dots are zero-length dashes with round caps.
+            LineStyle::Dotted => Some(vec![0.0, width * 2.0]),
+        }
+    }
+}
+
+// A shadow drawn under the label background and text.
+#[derive(PartialEq, Clone, Debug)]
+pub struct LabelShadow {
+    pub offset: kurbo::Vec2,
+    pub color: piet::Color,
+}
+
+// Describes how boxes are outlined and filled.
+#[derive(PartialEq, Clone, Debug)]
+pub struct BoxStyle {
+    pub line: LineStyle,
+    // The opacity of the fill with the box color, `0.0` disables the fill.
+    pub fill_opacity: f64,
+    pub corner_radius: f64,
+    // Draws only the corners of the box. The length of a bracket is a fraction of the shorter
+    // box side.
+    pub brackets: Option<f64>,
+    pub label_shadow: Option<LabelShadow>,
+}
+
+impl BoxStyle {
+    pub fn new() -> Self {
+        BoxStyle {
+            line: LineStyle::Solid,
+            fill_opacity: 0.0,
+            corner_radius: 0.0,
+            brackets: None,
+            label_shadow: None,
+        }
+    }
+
+    pub fn line(mut self, line: LineStyle) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn fill(mut self, opacity: f64) -> Self {
+        self.fill_opacity = opacity.max(0.0).min(1.0);
+        self
+    }
+
+    pub fn rounded(mut self, corner_radius: f64) -> Self {
+        self.corner_radius = corner_radius.max(0.0);
+        self
+    }
+
+    pub fn brackets(mut self, length: f64) -> Self {
+        self.brackets = Some(length.max(0.0).min(0.5));
+        self
+    }
+
+    pub fn label_shadow(mut self, offset: kurbo::Vec2, color: piet::Color) -> Self {
+        self.label_shadow = Some(LabelShadow { offset, color });
+        self
+    }
+
+    // Returns the corner radius clamped to the box size.
+    pub fn radius(&self, rect: kurbo::Rect) -> f64 {
+        self.corner_radius
+            .min(rect.width().abs() / 2.0)
+            .min(rect.height().abs() / 2.0)
+    }
+
+    // Returns the polylines of the corner brackets.
+    pub fn bracket_lines(&self, rect: kurbo::Rect) -> Vec<[kurbo::Point; 3]> {
+        let fraction = match self.brackets {
+            Some(fraction) => fraction,
+            None => return Vec::new(),
+        };
+        let rect = rect.abs();
+        let length = rect.width().min(rect.height()) * fraction;
+
+        [
+            (rect.x0, rect.y0, 1.0, 1.0),
+            (rect.x1, rect.y0, -1.0, 1.0),
+            (rect.x1, rect.y1, -1.0, -1.0),
+            (rect.x0, rect.y1, 1.0, -1.0),
+        ]
+        .iter()
+        .map(|&(x, y, dx, dy)| {
+            [
+                kurbo::Point::new(x, y + dy * length),
+                kurbo::Point::new(x, y),
+                kurbo::Point::new(x + dx * length, y),
+            ]
+        })
+        .collect()
+    }
+}
+
+impl Default for BoxStyle {
+    fn default() -> Self {
+        BoxStyle::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracket_lines() {
+        let style = BoxStyle::new().brackets(0.25);
+        let lines = style.bracket_lines(kurbo::Rect::new(0.0, 0.0, 40.0, 20.0));
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            [
+                kurbo::Point::new(0.0, 5.0),
+                kurbo::Point::new(0.0, 0.0),
+                kurbo::Point::new(5.0, 0.0)
+            ]
+        );
+        assert_eq!(lines[2][1], kurbo::Point::new(40.0, 20.0));
+        assert!(BoxStyle::new()
+            .bracket_lines(kurbo::Rect::new(0.0, 0.0, 1.0, 1.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn clamp_radius() {
+        let style = BoxStyle::new().rounded(20.0);
+        assert_eq!(style.radius(kurbo::Rect::new(0.0, 0.0, 30.0, 100.0)), 15.0);
+    }
+}
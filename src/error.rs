@@ -21,7 +21,79 @@ impl fmt::Debug for GenericError {
     }
 }
 
+// Collects the failures of every download attempt.
+pub struct DownloadError {
+    pub failures: Vec<(String, String)>,
+}
+
+impl std::error::Error for DownloadError {}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "All download attempts failed:")?;
+        for (url, failure) in self.failures.iter() {
+            write!(f, "\n  {}: {}", url, failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DownloadError {{ failures: {:?}}}", self.failures)
+    }
+}
+
+// A missing, malformed or untrusted signature.
+pub struct SignatureError {
+    pub message: String,
+}
+
+impl std::error::Error for SignatureError {}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl fmt::Debug for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SignatureError {{ message: {}}}", self.message)
+    }
+}
+
+// A file or a download whose content does not match the expected SHA-256 hash.
+pub struct ChecksumMismatch {
+    pub source: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The checksum of \"{}\" ({}) does not match the expected checksum value ({}).",
+            self.source, self.actual, self.expected
+        )
+    }
+}
+
+impl fmt::Debug for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ChecksumMismatch {{ source: {}, expected: {}, actual: {}}}",
+            self.source, self.expected, self.actual
+        )
+    }
+}
+
 pub enum Error {
+    #[cfg(feature = "tensorflow")]
     TensprFlowError(tensorflow::Status),
     ProtobufParseError(protobuf::text_format::ParseError),
     ShapeError(ndarray::ShapeError),
@@ -29,7 +101,14 @@ pub enum Error {
     LabelNotFound(ms_coco::LabelNotFound),
     IoError(io::Error),
     HttpError(minreq::Error),
+    DownloadError(DownloadError),
+    ChecksumMismatch(ChecksumMismatch),
+    SignatureError(SignatureError),
     GenericError(GenericError),
+    #[cfg(feature = "onnx")]
+    OnnxError(tract_onnx::prelude::TractError),
+    #[cfg(feature = "tflite")]
+    TfLiteError(tflite::Error),
 }
 
 impl fmt::Display for Error {
@@ -37,12 +116,20 @@ impl fmt::Display for Error {
         match *self {
             Error::IoError(ref e) => e.fmt(f),
             Error::HttpError(ref e) => e.fmt(f),
+            Error::DownloadError(ref e) => e.fmt(f),
+            Error::ChecksumMismatch(ref e) => e.fmt(f),
+            Error::SignatureError(ref e) => e.fmt(f),
+            #[cfg(feature = "tensorflow")]
             Error::TensprFlowError(ref e) => e.fmt(f),
             Error::ProtobufParseError(ref e) => e.fmt(f),
             Error::ShapeError(ref e) => e.fmt(f),
             Error::DrawingError(ref e) => e.fmt(f),
             Error::LabelNotFound(ref e) => e.fmt(f),
             Error::GenericError(ref e) => e.fmt(f),
+            #[cfg(feature = "onnx")]
+            Error::OnnxError(ref e) => e.fmt(f),
+            #[cfg(feature = "tflite")]
+            Error::TfLiteError(ref e) => e.fmt(f),
         }
     }
 }
@@ -52,12 +139,20 @@ impl fmt::Debug for Error {
         match *self {
             Error::IoError(ref e) => e.fmt(f),
             Error::HttpError(ref e) => e.fmt(f),
+            Error::DownloadError(ref e) => e.fmt(f),
+            Error::ChecksumMismatch(ref e) => e.fmt(f),
+            Error::SignatureError(ref e) => e.fmt(f),
+            #[cfg(feature = "tensorflow")]
             Error::TensprFlowError(ref e) => e.fmt(f),
             Error::ProtobufParseError(ref e) => e.fmt(f),
             Error::ShapeError(ref e) => e.fmt(f),
             Error::DrawingError(ref e) => e.fmt(f),
             Error::LabelNotFound(ref e) => e.fmt(f),
             Error::GenericError(ref e) => e.fmt(f),
+            #[cfg(feature = "onnx")]
+            Error::OnnxError(ref e) => e.fmt(f),
+            #[cfg(feature = "tflite")]
+            Error::TfLiteError(ref e) => e.fmt(f),
         }
     }
 }
@@ -67,12 +162,20 @@ impl error::Error for Error {
         match *self {
             Error::IoError(ref e) => Some(e),
             Error::HttpError(ref e) => Some(e),
+            Error::DownloadError(ref e) => Some(e),
+            Error::ChecksumMismatch(ref e) => Some(e),
+            Error::SignatureError(ref e) => Some(e),
+            #[cfg(feature = "tensorflow")]
             Error::TensprFlowError(ref e) => Some(e),
             Error::ProtobufParseError(ref e) => Some(e),
             Error::ShapeError(ref e) => Some(e),
             Error::DrawingError(ref e) => Some(e),
             Error::LabelNotFound(ref e) => Some(e),
             Error::GenericError(ref e) => Some(e),
+            #[cfg(feature = "onnx")]
+            Error::OnnxError(ref e) => Some(&**e),
+            #[cfg(feature = "tflite")]
+            Error::TfLiteError(ref e) => Some(e),
         }
     }
 }
@@ -89,6 +192,7 @@ impl From<minreq::Error> for Error {
     }
 }
 
+#[cfg(feature = "tensorflow")]
 impl From<tensorflow::Status> for Error {
     fn from(err: tensorflow::Status) -> Error {
         Error::TensprFlowError(err)
@@ -119,6 +223,20 @@ impl From<ms_coco::LabelNotFound> for Error {
     }
 }
 
+#[cfg(feature = "onnx")]
+impl From<tract_onnx::prelude::TractError> for Error {
+    fn from(err: tract_onnx::prelude::TractError) -> Error {
+        Error::OnnxError(err)
+    }
+}
+
+#[cfg(feature = "tflite")]
+impl From<tflite::Error> for Error {
+    fn from(err: tflite::Error) -> Error {
+        Error::TfLiteError(err)
+    }
+}
+
 impl From<&str> for Error {
     fn from(err: &str) -> Error {
         Error::GenericError(GenericError {
@@ -126,3 +244,9 @@ impl From<&str> for Error {
         })
     }
 }
+
+impl From<String> for Error {
+    fn from(err: String) -> Error {
+        Error::GenericError(GenericError { message: err })
+    }
+}
@@ -5,16 +5,44 @@ extern crate piet;
 
 #[macro_use]
 pub mod utils;
+pub mod analytics;
+#[cfg(feature = "async")]
+pub mod async_detector;
+#[cfg(feature = "tensorflow")]
+pub mod batching;
 pub mod cache;
+pub mod decoder;
+pub mod detection;
 pub mod drawing;
 pub mod error;
 pub mod ms_coco;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+#[cfg(feature = "tensorflow")]
 pub mod ssd_mobilenet;
+#[cfg(feature = "tflite")]
+pub mod ssd_tflite;
+pub mod tracking;
+pub mod video;
 
+pub use analytics::*;
+#[cfg(feature = "async")]
+pub use async_detector::*;
+#[cfg(feature = "tensorflow")]
+pub use batching::*;
 pub use cache::*;
+pub use decoder::*;
+pub use detection::*;
 pub use drawing::*;
 pub use error::*;
 pub use ms_coco::*;
+#[cfg(feature = "onnx")]
+pub use onnx::*;
+#[cfg(feature = "tensorflow")]
 pub use ssd_mobilenet::*;
+#[cfg(feature = "tflite")]
+pub use ssd_tflite::*;
+pub use tracking::*;
+pub use video::*;
 
 pub use piet::*;
@@ -3,6 +3,8 @@ pub mod protos;
 use crate::error;
 use std::collections;
 use std::fmt;
+use std::fs;
+use std::path;
 use std::result;
 
 #[derive(PartialEq)]
@@ -29,8 +31,17 @@ pub struct LabelMap {
 }
 
 impl LabelMap {
+    // Loads the built-in MS COCO label map.
     pub fn load() -> result::Result<Self, error::Error> {
-        let raw_data = include_str!("mscoco_label_map.pbtxt");
+        LabelMap::parse(include_str!("mscoco_label_map.pbtxt"))
+    }
+
+    // Loads a label map from a `.pbtxt` file.
+    pub fn from_file(file_path: &path::Path) -> result::Result<Self, error::Error> {
+        LabelMap::parse(&fs::read_to_string(file_path)?)
+    }
+
+    pub fn parse(raw_data: &str) -> result::Result<Self, error::Error> {
         let data: protos::labelmap::StringIntLabelMapProto =
             protobuf::text_format::parse_from_str(raw_data)?;
         Ok(LabelMap {
@@ -48,6 +59,14 @@ impl LabelMap {
             None => Err(LabelNotFound { id: *id }),
         }
     }
+
+    // Returns the ID of the label name.
+    pub fn get_label_id(&self, name: &str) -> Option<i32> {
+        self.data
+            .iter()
+            .find(|(_, label_name)| label_name.as_str() == name)
+            .map(|(id, _)| *id)
+    }
 }
 
 #[cfg(test)]
@@ -61,6 +80,14 @@ mod tests {
             .get_label_name(&17)
             .expect("Failed to get an item from the Label Map");
         assert_eq!(item, "cat".to_string());
+        assert_eq!(label_map.get_label_id("cat"), Some(17));
+    }
+
+    #[test]
+    fn parse_label_map() {
+        let label_map = LabelMap::parse("item { id: 1 display_name: \"dog\" }")
+            .expect("Failed to parse a Label Map");
+        assert_eq!(label_map.get_label_name(&1), Ok("dog".to_string()));
     }
 
     #[test]
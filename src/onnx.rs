@@ -0,0 +1,416 @@
+use crate::decoder;
+use crate::detection;
+use crate::error;
+use crate::ms_coco;
+
+use std::path;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+type OnnxPlan = TypedRunnableModel<TypedModel>;
+
+// The input size used when the model does not declare it.
+const DEFAULT_INPUT_SIZE: usize = 300;
+
+// Runs an ONNX export of SSD MobileNet v2 with the pure-Rust `tract` engine.
+//
+// The model is expected to be converted from the TensorFlow frozen graph (e.g. with
+// `tf2onnx`) and keep its `image_tensor` uint8 input. Its outputs are the `detection_boxes`,
+// `detection_classes` and `detection_scores` run by `shot`, or the `raw_outputs/box_encodings`
+// and `raw_outputs/class_predictions` decoded by `shot_raw`, optionally with the `:0` suffix of
+// the TensorFlow tensor names.
+//
+// `tract` does not implement every ONNX operator, notably `Loop`, so exports keeping the
+// TensorFlow postprocessing while loops are rejected by `load`. Such graphs are run by cutting
+// the export at the raw outputs, e.g. with `tf2onnx --outputs
+// raw_outputs/box_encodings:0,raw_outputs/class_predictions:0`.
+pub struct SSDMobileNetV2Onnx {
+    model: OnnxPlan,
+    label_map: ms_coco::LabelMap,
+    // The `(height, width)` images are resized to.
+    input_size: (usize, usize),
+    // The indices of the boxes, classes and scores outputs.
+    detection_outputs: Option<(usize, usize, usize)>,
+    // The indices of the box encodings and class predictions outputs.
+    raw_outputs: Option<(usize, usize)>,
+}
+
+impl SSDMobileNetV2Onnx {
+    // Loads the model. The input size is taken from the model or is 300x300 if the model
+    // accepts any size.
+    pub fn load(ssd_model_path: &path::Path) -> Result<Self, error::Error> {
+        SSDMobileNetV2Onnx::from_model(tract_onnx::onnx().model_for_path(ssd_model_path)?, None)
+    }
+
+    // Loads the model which accepts any input size and runs it on images of the given size.
+    pub fn load_with_input_size(
+        ssd_model_path: &path::Path,
+        width: usize,
+        height: usize,
+    ) -> Result<Self, error::Error> {
+        SSDMobileNetV2Onnx::from_model(
+            tract_onnx::onnx().model_for_path(ssd_model_path)?,
+            Some((height, width)),
+        )
+    }
+
+    fn from_model(
+        model: InferenceModel,
+        input_size: Option<(usize, usize)>,
+    ) -> Result<Self, error::Error> {
+        let mut unsupported: Vec<String> = model
+            .nodes()
+            .iter()
+            .filter_map(|node| {
+                let op_name = node.op.name();
+                let unimplemented = op_name
+                    .strip_prefix("Unimplemented(")
+                    .and_then(|op_name| op_name.strip_suffix(')'))
+                    .map(|op_name| op_name.to_string());
+                unimplemented
+            })
+            .collect();
+        if !unsupported.is_empty() {
+            unsupported.sort();
+            unsupported.dedup();
+            return Err(format!(
+                "The ONNX model uses operators tract does not support: {}",
+                unsupported.join(", ")
+            )
+            .into());
+        }
+
+        let label_map = ms_coco::LabelMap::load()?;
+        let (height, width) = match input_size {
+            Some(input_size) => input_size,
+            None => model_input_size(model.input_fact(0)?),
+        };
+        let model = model
+            .with_input_fact(0, u8::fact([1, height, width, 3]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+
+        let output_names = model
+            .model()
+            .output_outlets()?
+            .iter()
+            .map(|outlet| {
+                model
+                    .model()
+                    .outlet_label(*outlet)
+                    .unwrap_or(&model.model().node(outlet.node).name)
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+        let detection_outputs = match (
+            output_index(&output_names, "detection_boxes"),
+            output_index(&output_names, "detection_classes"),
+            output_index(&output_names, "detection_scores"),
+        ) {
+            (Some(boxes), Some(classes), Some(scores)) => Some((boxes, classes, scores)),
+            _ => None,
+        };
+        let raw_outputs = match (
+            output_index(&output_names, "raw_outputs/box_encodings"),
+            output_index(&output_names, "raw_outputs/class_predictions"),
+        ) {
+            (Some(box_encodings), Some(class_predictions)) => {
+                Some((box_encodings, class_predictions))
+            }
+            _ => None,
+        };
+        if detection_outputs.is_none() && raw_outputs.is_none() {
+            return Err(format!(
+                "The ONNX model has neither the detection nor the raw SSD outputs (found {:?})",
+                output_names
+            )
+            .into());
+        }
+
+        Ok(SSDMobileNetV2Onnx {
+            model,
+            label_map,
+            input_size: (height, width),
+            detection_outputs,
+            raw_outputs,
+        })
+    }
+
+    pub fn shot(
+        &self,
+        img: &image::DynamicImage,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let (boxes_output, classes_output, scores_output) = self
+            .detection_outputs
+            .ok_or("The ONNX model has only the raw SSD outputs, use `shot_raw` to decode them")?;
+        let outputs = self.run(img)?;
+
+        detection::detection_boxes(
+            &self.label_map,
+            outputs[boxes_output].as_slice::<f32>()?,
+            outputs[classes_output].as_slice::<f32>()?,
+            outputs[scores_output].as_slice::<f32>()?,
+        )
+    }
+
+    // Runs the model and decodes the raw SSD head outputs with the decoder.
+    //
+    // This works with exports cut at the `raw_outputs/box_encodings` and
+    // `raw_outputs/class_predictions` nodes, before the postprocessing `tract` can not run.
+    pub fn shot_raw(
+        &self,
+        img: &image::DynamicImage,
+        decoder: &decoder::SSDDecoder,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let (box_encodings_output, class_predictions_output) = self
+            .raw_outputs
+            .ok_or("The ONNX model has only the postprocessed outputs, use `shot` to run it")?;
+        let outputs = self.run(img)?;
+
+        decoder.decode(
+            outputs[box_encodings_output].as_slice::<f32>()?,
+            outputs[class_predictions_output].as_slice::<f32>()?,
+        )
+    }
+
+    fn run(&self, img: &image::DynamicImage) -> Result<TVec<TValue>, error::Error> {
+        let (height, width) = self.input_size;
+        let img_min = img.resize_exact(
+            width as u32,
+            height as u32,
+            image::imageops::FilterType::Nearest,
+        );
+        let input_image_tensor =
+            Tensor::from_shape(&[1, height, width, 3], &img_min.to_rgb().into_raw())?;
+
+        Ok(self.model.run(tvec!(input_image_tensor.into()))?)
+    }
+}
+
+// Returns the `(height, width)` of the NHWC input if the model declares it.
+fn model_input_size(fact: &InferenceFact) -> (usize, usize) {
+    let dims: Vec<Option<usize>> = fact
+        .shape
+        .dims()
+        .map(|dim| dim.concretize().and_then(|dim| dim.to_usize().ok()))
+        .collect();
+
+    match dims.as_slice() {
+        [_, Some(height), Some(width), _] => (*height, *width),
+        _ => (DEFAULT_INPUT_SIZE, DEFAULT_INPUT_SIZE),
+    }
+}
+
+// Returns the index of the output named exactly `name`, or `name:0` as exported from a
+// TensorFlow graph.
+fn output_index(output_names: &[String], name: &str) -> Option<usize> {
+    let tensor_name = format!("{}:0", name);
+    output_names
+        .iter()
+        .position(|output_name| *output_name == name || *output_name == tensor_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tract_onnx::pb;
+
+    fn value_info(
+        name: &str,
+        elem_type: pb::tensor_proto::DataType,
+        dims: &[i64],
+    ) -> pb::ValueInfoProto {
+        let dim = dims
+            .iter()
+            .map(|dim| pb::tensor_shape_proto::Dimension {
+                value: Some(if *dim > 0 {
+                    pb::tensor_shape_proto::dimension::Value::DimValue(*dim)
+                } else {
+                    pb::tensor_shape_proto::dimension::Value::DimParam("N".to_string())
+                }),
+                ..Default::default()
+            })
+            .collect();
+
+        pb::ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(pb::TypeProto {
+                value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                    elem_type: elem_type as i32,
+                    shape: Some(pb::TensorShapeProto { dim }),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn float_tensor(name: &str, dims: &[i64], values: &[f32]) -> pb::TensorProto {
+        pb::TensorProto {
+            name: name.to_string(),
+            dims: dims.to_vec(),
+            data_type: pb::tensor_proto::DataType::Float as i32,
+            float_data: values.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn node(op_type: &str, inputs: &[&str], output: &str) -> pb::NodeProto {
+        pb::NodeProto {
+            op_type: op_type.to_string(),
+            input: inputs.iter().map(|input| input.to_string()).collect(),
+            output: vec![output.to_string()],
+            ..Default::default()
+        }
+    }
+
+    // Builds a model with the `image_tensor` input of the given shape.
+    fn onnx_model(
+        input_dims: &[i64],
+        nodes: Vec<pb::NodeProto>,
+        initializer: Vec<pb::TensorProto>,
+        outputs: &[&str],
+    ) -> InferenceModel {
+        let proto = pb::ModelProto {
+            ir_version: 7,
+            opset_import: vec![pb::OperatorSetIdProto {
+                domain: String::new(),
+                version: 11,
+            }],
+            graph: Some(pb::GraphProto {
+                node: nodes,
+                initializer,
+                input: vec![value_info(
+                    "image_tensor:0",
+                    pb::tensor_proto::DataType::Uint8,
+                    input_dims,
+                )],
+                output: outputs
+                    .iter()
+                    .map(|output| pb::ValueInfoProto {
+                        name: output.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        tract_onnx::onnx().model_for_proto_model(&proto).unwrap()
+    }
+
+    #[test]
+    fn resolve_exact_output_names() {
+        let output_names: Vec<String> = vec![
+            "detection_boxes_1".to_string(),
+            "detection_boxes:0".to_string(),
+            "detection_scores".to_string(),
+        ];
+
+        assert_eq!(output_index(&output_names, "detection_boxes"), Some(1));
+        assert_eq!(output_index(&output_names, "detection_scores"), Some(2));
+        assert_eq!(output_index(&output_names, "detection_classes"), None);
+        assert_eq!(output_index(&output_names, "detection"), None);
+    }
+
+    #[test]
+    fn run_model_with_detection_outputs() {
+        let boxes = [0.1, 0.2, 0.3, 0.4, 0.5, 0.5, 0.9, 0.9];
+        let model = onnx_model(
+            &[-1, 32, 24, 3],
+            vec![
+                node("Identity", &["boxes"], "detection_boxes_1"),
+                node("Identity", &["boxes"], "detection_boxes:0"),
+                node("Identity", &["classes"], "detection_classes:0"),
+                node("Identity", &["scores"], "detection_scores:0"),
+            ],
+            vec![
+                float_tensor("boxes", &[1, 2, 4], &boxes),
+                float_tensor("classes", &[1, 2], &[1.0, 3.0]),
+                float_tensor("scores", &[1, 2], &[0.9, 0.8]),
+            ],
+            &[
+                "detection_boxes_1",
+                "detection_boxes:0",
+                "detection_classes:0",
+                "detection_scores:0",
+            ],
+        );
+
+        let ssd_net = SSDMobileNetV2Onnx::from_model(model, None).unwrap();
+        assert_eq!(ssd_net.input_size, (32, 24));
+        let detection_boxes = ssd_net
+            .shot(&image::DynamicImage::new_rgb8(64, 48))
+            .unwrap();
+
+        let labels: Vec<&str> = detection_boxes.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, vec!["person", "car"]);
+        assert_eq!(detection_boxes[0].x1, 0.2);
+    }
+
+    #[test]
+    fn decode_raw_outputs() {
+        // Background, person, bicycle for two anchors.
+        let class_predictions = [-5.0, 5.0, -5.0, -5.0, -5.0, 5.0];
+        let model = onnx_model(
+            &[-1, 16, 16, 3],
+            vec![
+                node(
+                    "Identity",
+                    &["box_encodings"],
+                    "raw_outputs/box_encodings:0",
+                ),
+                node(
+                    "Identity",
+                    &["class_predictions"],
+                    "raw_outputs/class_predictions:0",
+                ),
+            ],
+            vec![
+                float_tensor("box_encodings", &[1, 2, 4], &[0.0; 8]),
+                float_tensor("class_predictions", &[1, 2, 3], &class_predictions),
+            ],
+            &[
+                "raw_outputs/box_encodings:0",
+                "raw_outputs/class_predictions:0",
+            ],
+        );
+        let anchor = |y_center, x_center| decoder::AnchorBox {
+            y_center,
+            x_center,
+            height: 0.2,
+            width: 0.2,
+        };
+        let decoder = decoder::SSDDecoder::new()
+            .unwrap()
+            .anchors(vec![anchor(0.3, 0.3), anchor(0.7, 0.7)])
+            .score_threshold(0.5);
+
+        let ssd_net = SSDMobileNetV2Onnx::from_model(model, None).unwrap();
+        let img = image::DynamicImage::new_rgb8(16, 16);
+        let detection_boxes = ssd_net.shot_raw(&img, &decoder).unwrap();
+
+        let labels: Vec<&str> = detection_boxes.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels, vec!["person", "bicycle"]);
+        assert!((detection_boxes[1].x1 - 0.6).abs() < 1e-6);
+        // The postprocessed outputs are not exported.
+        assert!(ssd_net.shot(&img).is_err());
+    }
+
+    #[test]
+    fn reject_unsupported_operators() {
+        let model = onnx_model(
+            &[-1, -1, -1, 3],
+            vec![node("Loop", &["image_tensor:0"], "detection_boxes:0")],
+            vec![],
+            &["detection_boxes:0"],
+        );
+
+        let err = SSDMobileNetV2Onnx::from_model(model, None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("not support: Loop"), "{}", err);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::cache;
+use crate::decoder;
+use crate::detection;
 use crate::error;
 use crate::ms_coco;
 use crate::utils;
@@ -5,15 +8,7 @@ use crate::utils;
 use std::path;
 use tensorflow as tf;
 
-#[derive(PartialEq, Clone, Debug)]
-pub struct DetectionBox {
-    pub x1: f32,
-    pub y1: f32,
-    pub x2: f32,
-    pub y2: f32,
-    pub label: String,
-    pub score: f32,
-}
+pub use crate::detection::DetectionBox;
 
 type SSDTensors = (
     tensorflow::Tensor<f32>,
@@ -44,31 +39,49 @@ impl SSDMobileNetV2 {
         })
     }
 
-    fn transform_image(
+    // Loads the model from the cache, downloading it if needed.
+    //
+    // The graph is only loaded once it passes the verification configured in `cache`, e.g.
+    // the signature check against its trusted keys.
+    pub fn from_cache(
+        cache: &cache::CacheConfig,
+        model: &cache::ModelDescriptor,
+    ) -> Result<Self, error::Error> {
+        let ssd_graph_path = cache.get_or_load_model(model)?;
+        let label_map = cache.get_label_map(model)?;
+
+        Ok(SSDMobileNetV2::load(&ssd_graph_path)?.with_label_map(label_map))
+    }
+
+    // Replaces the MS COCO label map, e.g. with `cache::get_label_map` of the loaded model.
+    pub fn with_label_map(mut self, label_map: ms_coco::LabelMap) -> Self {
+        self.label_map = label_map;
+        self
+    }
+
+    fn transform_images(
         &self,
-        img: &image::DynamicImage,
+        imgs: &[&image::DynamicImage],
     ) -> Result<(tf::Operation, tf::Tensor<u8>), error::Error> {
         let (height, width) = (300, 300);
-        let img_min = img.resize_exact(width, height, image::imageops::FilterType::Nearest);
+        let image_size = (height * width * 3) as usize;
 
-        let image_array = ndarray::Array::from_shape_vec(
-            (height as usize, width as usize, 3),
-            img_min.to_rgb().to_vec(),
-        )?;
-        let image_array_expanded = image_array.insert_axis(ndarray::Axis(0));
-        let image_array_slice = image_array_expanded
-            .as_slice()
-            .ok_or_else(|| "Failed to convert the data array to slice")?;
+        let mut image_array = Vec::with_capacity(image_size * imgs.len());
+        for img in imgs.iter() {
+            let img_min = img.resize_exact(width, height, image::imageops::FilterType::Nearest);
+            image_array.extend_from_slice(&img_min.to_rgb().to_vec());
+        }
 
         let image_tensor_op = self.graph.operation_by_name_required("image_tensor")?;
-        let input_image_tensor = tf::Tensor::new(&[1, u64::from(height), u64::from(width), 3])
-            .with_values(image_array_slice)?;
+        let input_image_tensor =
+            tf::Tensor::new(&[imgs.len() as u64, u64::from(height), u64::from(width), 3])
+                .with_values(&image_array)?;
 
         Ok((image_tensor_op, input_image_tensor))
     }
 
-    fn run(&self, img: &image::DynamicImage) -> Result<SSDTensors, error::Error> {
-        let (image_tensor_op, input_image_tensor) = self.transform_image(img)?;
+    fn run(&self, imgs: &[&image::DynamicImage]) -> Result<SSDTensors, error::Error> {
+        let (image_tensor_op, input_image_tensor) = self.transform_images(imgs)?;
 
         let mut args = tf::SessionRunArgs::new();
         args.add_feed(&image_tensor_op, 0, &input_image_tensor);
@@ -94,30 +107,74 @@ impl SSDMobileNetV2 {
     }
 
     pub fn shot(&self, img: &image::DynamicImage) -> Result<Vec<DetectionBox>, error::Error> {
-        let (_, boxes_tensor, classes_tensor, scores_tensor) = self.run(img)?;
+        let (_, boxes_tensor, classes_tensor, scores_tensor) = self.run(&[img])?;
 
-        let label_names: Result<Vec<String>, ms_coco::LabelNotFound> = classes_tensor
-            .iter()
-            .map(|class| self.label_map.get_label_name(&(*class as i32)))
-            .collect();
-        let label_names = label_names?;
+        detection::detection_boxes(
+            &self.label_map,
+            &boxes_tensor,
+            &classes_tensor,
+            &scores_tensor,
+        )
+    }
+
+    // Runs the graph up to the raw box predictor outputs and decodes them with `decoder`.
+    //
+    // This works with graphs that were exported without the postprocessing ops, as long as
+    // they keep the `raw_outputs/box_encodings` and `raw_outputs/class_predictions` nodes.
+    pub fn shot_raw(
+        &self,
+        img: &image::DynamicImage,
+        decoder: &decoder::SSDDecoder,
+    ) -> Result<Vec<DetectionBox>, error::Error> {
+        let (image_tensor_op, input_image_tensor) = self.transform_images(&[img])?;
 
-        let boxes: Vec<DetectionBox> = zip!(
-            boxes_tensor.chunks_exact(4),
-            label_names.iter(),
-            scores_tensor.iter()
+        let mut args = tf::SessionRunArgs::new();
+        args.add_feed(&image_tensor_op, 0, &input_image_tensor);
+
+        let box_encodings = self
+            .graph
+            .operation_by_name_required("raw_outputs/box_encodings")?;
+        let class_predictions = self
+            .graph
+            .operation_by_name_required("raw_outputs/class_predictions")?;
+
+        let box_encodings_token = args.request_fetch(&box_encodings, 0);
+        let class_predictions_token = args.request_fetch(&class_predictions, 0);
+
+        self.session.run(&mut args)?;
+
+        decoder.decode(
+            &args.fetch::<f32>(box_encodings_token)?,
+            &args.fetch::<f32>(class_predictions_token)?,
         )
-        .map(|(bbox, (label, score))| DetectionBox {
-            y1: bbox[0],
-            x1: bbox[1],
-            y2: bbox[2],
-            x2: bbox[3],
-            label: label.to_string(),
-            score: *score,
-        })
-        .collect();
+    }
 
-        Ok(boxes)
+    // Runs detection over several images as one batched tensor.
+    //
+    // Returns the detected boxes for each image in the same order as the input images.
+    pub fn shot_batch(
+        &self,
+        imgs: &[&image::DynamicImage],
+    ) -> Result<Vec<Vec<DetectionBox>>, error::Error> {
+        if imgs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (_, boxes_tensor, classes_tensor, scores_tensor) = self.run(imgs)?;
+        let detections = classes_tensor.len() / imgs.len();
+        if detections == 0 {
+            return Ok(vec![Vec::new(); imgs.len()]);
+        }
+
+        zip!(
+            boxes_tensor.chunks_exact(detections * 4),
+            classes_tensor.chunks_exact(detections),
+            scores_tensor.chunks_exact(detections)
+        )
+        .map(|(boxes, (classes, scores))| {
+            detection::detection_boxes(&self.label_map, boxes, classes, scores)
+        })
+        .collect()
     }
 }
 
@@ -0,0 +1,389 @@
+use crate::detection;
+use crate::error;
+use crate::ms_coco;
+
+use std::convert::TryInto;
+use std::fs;
+use std::path;
+use std::sync::Mutex;
+use tflite::context::ElementKind;
+use tflite::ops::builtin::BuiltinOpResolver;
+use tflite::{FlatBufferModel, Interpreter, InterpreterBuilder};
+
+// The affine mapping of uint8 tensor values to real values: `scale * (value - zero_point)`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct QuantizationParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl QuantizationParams {
+    pub fn dequantize(&self, data: &[u8]) -> Vec<f32> {
+        data.iter()
+            .map(|value| self.scale * (i32::from(*value) - self.zero_point) as f32)
+            .collect()
+    }
+}
+
+// Runs SSD MobileNet `.tflite` models ending with the `TFLite_Detection_PostProcess` op.
+//
+// Both float and uint8-quantized models are supported. The postprocess op emits float
+// outputs, but outputs quantized by the converter are dequantized with the parameters stored
+// with each output tensor in the model.
+pub struct SSDMobileNetTfLite {
+    interpreter: Mutex<Interpreter<'static, BuiltinOpResolver>>,
+    label_map: ms_coco::LabelMap,
+    // The quantization parameters of the model tensors, indexed like the interpreter tensors.
+    quantization: Vec<Option<QuantizationParams>>,
+}
+
+impl SSDMobileNetTfLite {
+    pub fn load(ssd_model_path: &path::Path) -> Result<Self, error::Error> {
+        let label_map = ms_coco::LabelMap::load()?;
+        let quantization = read_quantization(&fs::read(ssd_model_path)?)?;
+        let model = FlatBufferModel::build_from_file(ssd_model_path)?;
+        let resolver = BuiltinOpResolver::default();
+        let mut interpreter = InterpreterBuilder::new(model, resolver)?.build()?;
+        interpreter.allocate_tensors()?;
+
+        if interpreter.inputs().len() != 1 || interpreter.outputs().len() < 3 {
+            return Err("The TFLite model is not an SSD model with a postprocess op".into());
+        }
+
+        Ok(SSDMobileNetTfLite {
+            interpreter: Mutex::new(interpreter),
+            label_map,
+            quantization,
+        })
+    }
+
+    pub fn shot(
+        &self,
+        img: &image::DynamicImage,
+    ) -> Result<Vec<detection::DetectionBox>, error::Error> {
+        let mut interpreter = self
+            .interpreter
+            .lock()
+            .map_err(|_| "The TFLite interpreter is poisoned")?;
+
+        let input = interpreter.inputs()[0];
+        let input_info = interpreter
+            .tensor_info(input)
+            .ok_or_else(|| "Failed to get the TFLite input tensor")?;
+        let (height, width) = match input_info.dims.as_slice() {
+            &[_, height, width, 3] => (height as u32, width as u32),
+            _ => return Err(format!("Unsupported input shape {:?}", input_info.dims).into()),
+        };
+
+        let img_min = img
+            .resize_exact(width, height, image::imageops::FilterType::Nearest)
+            .to_rgb()
+            .into_raw();
+        match input_info.element_kind {
+            ElementKind::kTfLiteUInt8 => {
+                interpreter
+                    .tensor_data_mut::<u8>(input)?
+                    .copy_from_slice(&img_min);
+            }
+            ElementKind::kTfLiteFloat32 => {
+                let input_data = interpreter.tensor_data_mut::<f32>(input)?;
+                for (dst, src) in input_data.iter_mut().zip(img_min.iter()) {
+                    *dst = (f32::from(*src) - 127.5) / 127.5;
+                }
+            }
+            ref kind => return Err(format!("Unsupported input type {:?}", kind).into()),
+        }
+
+        interpreter.invoke()?;
+
+        let outputs = interpreter.outputs().to_vec();
+        let boxes = self.output(&interpreter, outputs[0])?;
+        let classes = self.output(&interpreter, outputs[1])?;
+        let scores = self.output(&interpreter, outputs[2])?;
+        let count = match outputs.get(3) {
+            Some(num_detections) => self
+                .output(&interpreter, *num_detections)?
+                .first()
+                .map_or(scores.len(), |count| *count as usize),
+            None => scores.len(),
+        }
+        .min(scores.len());
+
+        // The postprocess op reports class indices without the background class, while the
+        // label map IDs start from 1.
+        let classes: Vec<f32> = classes.iter().map(|class| class + 1.0).collect();
+
+        detection::detection_boxes(
+            &self.label_map,
+            &boxes[..count * 4],
+            &classes[..count],
+            &scores[..count],
+        )
+    }
+
+    fn output(
+        &self,
+        interpreter: &Interpreter<'static, BuiltinOpResolver>,
+        tensor: tflite::TensorIndex,
+    ) -> Result<Vec<f32>, error::Error> {
+        let info = interpreter
+            .tensor_info(tensor)
+            .ok_or_else(|| "Failed to get the TFLite output tensor")?;
+
+        match info.element_kind {
+            ElementKind::kTfLiteFloat32 => Ok(interpreter.tensor_data::<f32>(tensor)?.to_vec()),
+            ElementKind::kTfLiteUInt8 => {
+                let params = self
+                    .quantization
+                    .get(tensor as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or_else(|| {
+                        error::Error::from(format!(
+                            "The \"{}\" output is quantized, but has no quantization parameters",
+                            info.name
+                        ))
+                    })?;
+                Ok(params.dequantize(interpreter.tensor_data::<u8>(tensor)?))
+            }
+            ref kind => Err(format!("Unsupported output type {:?}", kind).into()),
+        }
+    }
+}
+
+// Reads the per-tensor quantization parameters of the main subgraph tensors from the `.tflite`
+// flatbuffer. Tensors without parameters map to `None`.
+fn read_quantization(model_data: &[u8]) -> Result<Vec<Option<QuantizationParams>>, error::Error> {
+    // The fields of the `Model`, `SubGraph`, `Tensor` and `QuantizationParameters` tables
+    // as declared in the TFLite schema.
+    const MODEL_SUBGRAPHS: usize = 2;
+    const SUBGRAPH_TENSORS: usize = 0;
+    const TENSOR_QUANTIZATION: usize = 4;
+    const QUANTIZATION_SCALE: usize = 2;
+    const QUANTIZATION_ZERO_POINT: usize = 3;
+
+    let flatbuffer = Flatbuffer(model_data);
+    let tensors = (|| {
+        let model = flatbuffer.offset(0)?;
+        let subgraphs = flatbuffer.vector(flatbuffer.field(model, MODEL_SUBGRAPHS)?)?;
+        let subgraph = flatbuffer.offset(subgraphs.first()?)?;
+        let tensors = flatbuffer.vector(flatbuffer.field(subgraph, SUBGRAPH_TENSORS)?)?;
+
+        tensors
+            .map(|tensor| {
+                let tensor = flatbuffer.offset(tensor)?;
+                let quantization = match flatbuffer.field(tensor, TENSOR_QUANTIZATION) {
+                    Some(field) => flatbuffer.offset(field)?,
+                    None => return Some(None),
+                };
+                let scale = flatbuffer
+                    .field(quantization, QUANTIZATION_SCALE)
+                    .and_then(|field| flatbuffer.vector(field)?.first())
+                    .map(|scale| flatbuffer.read::<[u8; 4]>(scale).map(f32::from_le_bytes));
+                let zero_point = flatbuffer
+                    .field(quantization, QUANTIZATION_ZERO_POINT)
+                    .and_then(|field| flatbuffer.vector(field)?.first())
+                    .map(|zero_point| {
+                        flatbuffer
+                            .read::<[u8; 8]>(zero_point)
+                            .map(i64::from_le_bytes)
+                    });
+
+                Some(match scale {
+                    Some(scale) => Some(QuantizationParams {
+                        scale: scale?,
+                        zero_point: zero_point.unwrap_or(Some(0))? as i32,
+                    }),
+                    None => None,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+    })();
+
+    tensors.ok_or_else(|| "The TFLite model is not a valid flatbuffer".into())
+}
+
+// Reads flatbuffer tables by their field positions. All accessors return `None` on data that
+// is out of bounds.
+struct Flatbuffer<'a>(&'a [u8]);
+
+impl<'a> Flatbuffer<'a> {
+    fn read<T: for<'b> std::convert::TryFrom<&'b [u8]>>(&self, position: usize) -> Option<T> {
+        let size = std::mem::size_of::<T>();
+        self.0
+            .get(position..position.checked_add(size)?)?
+            .try_into()
+            .ok()
+    }
+
+    fn u16(&self, position: usize) -> Option<usize> {
+        self.read::<[u8; 2]>(position)
+            .map(|bytes| u16::from_le_bytes(bytes) as usize)
+    }
+
+    fn u32(&self, position: usize) -> Option<usize> {
+        self.read::<[u8; 4]>(position)
+            .map(|bytes| u32::from_le_bytes(bytes) as usize)
+    }
+
+    // Follows the unsigned offset stored at the position.
+    fn offset(&self, position: usize) -> Option<usize> {
+        position.checked_add(self.u32(position)?)
+    }
+
+    // Returns the position of the field of the table or `None` if it is not set.
+    fn field(&self, table: usize, index: usize) -> Option<usize> {
+        let vtable_offset = self.read::<[u8; 4]>(table).map(i32::from_le_bytes)? as i64;
+        let vtable = (table as i64).checked_sub(vtable_offset)?;
+        if vtable < 0 {
+            return None;
+        }
+        let vtable = vtable as usize;
+
+        let entry = 4 + 2 * index;
+        if entry >= self.u16(vtable)? {
+            return None;
+        }
+        match self.u16(vtable + entry)? {
+            0 => None,
+            field_offset => table.checked_add(field_offset),
+        }
+    }
+
+    // Returns the element positions of the vector the field points to.
+    fn vector(&self, field: usize) -> Option<VectorElements> {
+        let vector = self.offset(field)?;
+        Some(VectorElements {
+            start: vector + 4,
+            len: self.u32(vector)?,
+            index: 0,
+        })
+    }
+}
+
+// Iterates over the element positions of a vector of 4 byte elements. `first` works for any
+// element size.
+struct VectorElements {
+    start: usize,
+    len: usize,
+    index: usize,
+}
+
+impl VectorElements {
+    fn first(&self) -> Option<usize> {
+        if self.len > 0 {
+            Some(self.start)
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for VectorElements {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.index < self.len {
+            self.index += 1;
+            Some(self.start + 4 * (self.index - 1))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds flatbuffers whose tables have every field up to the given count set.
+    struct Builder(Vec<u8>);
+
+    impl Builder {
+        fn table(&mut self, fields: usize) -> usize {
+            let vtable = self.0.len();
+            self.0.extend(&((4 + 2 * fields) as u16).to_le_bytes());
+            self.0.extend(&((4 + 4 * fields) as u16).to_le_bytes());
+            for index in 0..fields {
+                self.0.extend(&((4 + 4 * index) as u16).to_le_bytes());
+            }
+            let table = self.0.len();
+            self.0.extend(&((table - vtable) as i32).to_le_bytes());
+            self.0.resize(table + 4 + 4 * fields, 0);
+            table
+        }
+
+        fn vector(&mut self, elements: &[&[u8]]) -> usize {
+            let vector = self.0.len();
+            self.0.extend(&(elements.len() as u32).to_le_bytes());
+            for element in elements {
+                self.0.extend(*element);
+            }
+            vector
+        }
+
+        // Points the unsigned offset at the position to the target.
+        fn link(&mut self, position: usize, target: usize) {
+            let offset = ((target - position) as u32).to_le_bytes();
+            self.0[position..position + 4].copy_from_slice(&offset);
+        }
+    }
+
+    #[test]
+    fn dequantize() {
+        let params = QuantizationParams {
+            scale: 0.5,
+            zero_point: 128,
+        };
+        assert_eq!(params.dequantize(&[128, 130, 0]), vec![0.0, 1.0, -64.0]);
+    }
+
+    #[test]
+    fn read_tensor_quantization() {
+        let mut builder = Builder(vec![0; 8]);
+        builder.0[4..8].copy_from_slice(b"TFL3");
+        let model = builder.table(3);
+        builder.link(0, model);
+        let subgraphs = builder.vector(&[&[0; 4]]);
+        builder.link(model + 4 + 4 * 2, subgraphs);
+        let subgraph = builder.table(1);
+        builder.link(subgraphs + 4, subgraph);
+
+        let tensors = builder.vector(&[&[0; 4], &[0; 4], &[0; 4]]);
+        builder.link(subgraph + 4, tensors);
+        let params = [(0.1f32, 128i64), (2.0, 0)];
+        for (index, (scale, zero_point)) in params.iter().enumerate() {
+            let tensor = builder.table(5);
+            builder.link(tensors + 4 + 4 * index, tensor);
+            let quantization = builder.table(4);
+            builder.link(tensor + 4 + 4 * 4, quantization);
+            let scales = builder.vector(&[&scale.to_le_bytes()]);
+            builder.link(quantization + 4 + 4 * 2, scales);
+            let zero_points = builder.vector(&[&zero_point.to_le_bytes()]);
+            builder.link(quantization + 4 + 4 * 3, zero_points);
+        }
+        // A float tensor without quantization.
+        let tensor = builder.table(4);
+        builder.link(tensors + 4 + 4 * 2, tensor);
+
+        let quantization = read_quantization(&builder.0).unwrap();
+        assert_eq!(
+            quantization,
+            vec![
+                Some(QuantizationParams {
+                    scale: 0.1,
+                    zero_point: 128
+                }),
+                Some(QuantizationParams {
+                    scale: 2.0,
+                    zero_point: 0
+                }),
+                None
+            ]
+        );
+        // Every output is dequantized with its own parameters.
+        assert_eq!(quantization[0].unwrap().dequantize(&[138]), vec![1.0]);
+        assert_eq!(quantization[1].unwrap().dequantize(&[3]), vec![6.0]);
+        assert!(read_quantization(&builder.0[..40]).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::detection;
+
+const MEASUREMENT_NOISE: f32 = 1e-4;
+const POSITION_NOISE: f32 = 1e-5;
+const VELOCITY_NOISE: f32 = 1e-5;
+const INITIAL_VELOCITY_VARIANCE: f32 = 1e-2;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct TrackedBox {
+    pub id: u64,
+    pub detection: detection::DetectionBox,
+}
+
+// A constant velocity Kalman filter for one box coordinate.
+//
+// The box center and size are filtered independently, which keeps the covariance at 2x2 per
+// axis instead of the full joint matrix used by the reference SORT implementation.
+#[derive(Clone, Debug)]
+struct KalmanAxis {
+    position: f32,
+    velocity: f32,
+    covariance: [[f32; 2]; 2],
+}
+
+impl KalmanAxis {
+    fn new(position: f32) -> Self {
+        KalmanAxis {
+            position,
+            velocity: 0.0,
+            covariance: [[MEASUREMENT_NOISE, 0.0], [0.0, INITIAL_VELOCITY_VARIANCE]],
+        }
+    }
+
+    fn predict(&mut self) {
+        let [[p00, p01], [p10, p11]] = self.covariance;
+        self.position += self.velocity;
+        self.covariance = [
+            [p00 + p01 + p10 + p11 + POSITION_NOISE, p01 + p11],
+            [p10 + p11, p11 + VELOCITY_NOISE],
+        ];
+    }
+
+    fn update(&mut self, measurement: f32) {
+        let [[p00, p01], [p10, p11]] = self.covariance;
+        let residual = measurement - self.position;
+        let innovation = p00 + MEASUREMENT_NOISE;
+        let (k0, k1) = (p00 / innovation, p10 / innovation);
+
+        self.position += k0 * residual;
+        self.velocity += k1 * residual;
+        self.covariance = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+struct Track {
+    id: u64,
+    detection: detection::DetectionBox,
+    // Center x, center y, width and height.
+    axes: [KalmanAxis; 4],
+    hits: u32,
+    time_since_update: u32,
+}
+
+impl Track {
+    fn new(id: u64, detection: &detection::DetectionBox) -> Self {
+        let [cx, cy, w, h] = box_to_measurement(detection);
+        Track {
+            id,
+            detection: detection.clone(),
+            axes: [
+                KalmanAxis::new(cx),
+                KalmanAxis::new(cy),
+                KalmanAxis::new(w),
+                KalmanAxis::new(h),
+            ],
+            hits: 1,
+            time_since_update: 0,
+        }
+    }
+
+    fn predict(&mut self) {
+        for axis in self.axes.iter_mut() {
+            axis.predict();
+        }
+        self.time_since_update += 1;
+        self.detection = self.state_box();
+    }
+
+    fn update(&mut self, detection: &detection::DetectionBox) {
+        for (axis, measurement) in self.axes.iter_mut().zip(&box_to_measurement(detection)) {
+            axis.update(*measurement);
+        }
+        self.hits += 1;
+        self.time_since_update = 0;
+        self.detection = detection::DetectionBox {
+            label: detection.label.clone(),
+            score: detection.score,
+            ..self.state_box()
+        };
+    }
+
+    fn state_box(&self) -> detection::DetectionBox {
+        let (cx, cy) = (self.axes[0].position, self.axes[1].position);
+        let (w, h) = (
+            self.axes[2].position.max(0.0),
+            self.axes[3].position.max(0.0),
+        );
+        detection::DetectionBox {
+            x1: cx - w / 2.0,
+            y1: cy - h / 2.0,
+            x2: cx + w / 2.0,
+            y2: cy + h / 2.0,
+            label: self.detection.label.clone(),
+            score: self.detection.score,
+        }
+    }
+}
+
+fn box_to_measurement(detection: &detection::DetectionBox) -> [f32; 4] {
+    [
+        (detection.x1 + detection.x2) / 2.0,
+        (detection.y1 + detection.y2) / 2.0,
+        detection.width(),
+        detection.height(),
+    ]
+}
+
+// Assigns stable IDs to detections across successive frames (SORT-style).
+//
+// Every frame the tracks are moved by their motion model, matched to the new detections of the
+// same label by IoU and corrected by the matched boxes. Unmatched detections start new tracks,
+// and tracks that have not been matched for more than `max_age` frames are dropped, so objects
+// occluded for a few frames keep their IDs.
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+    frame_count: u32,
+    max_age: u32,
+    min_hits: u32,
+    iou_threshold: f32,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Tracker {
+            tracks: Vec::new(),
+            next_id: 1,
+            frame_count: 0,
+            max_age: 30,
+            min_hits: 3,
+            iou_threshold: 0.3,
+        }
+    }
+
+    // Sets the number of frames a track survives without matching detections.
+    pub fn max_age(mut self, max_age: u32) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    // Sets the number of matched frames required before a track is reported.
+    pub fn min_hits(mut self, min_hits: u32) -> Self {
+        self.min_hits = min_hits;
+        self
+    }
+
+    pub fn iou_threshold(mut self, iou_threshold: f32) -> Self {
+        self.iou_threshold = iou_threshold;
+        self
+    }
+
+    // Consumes the detections of the next frame and returns the confirmed tracks matched in it.
+    //
+    // The detections are expected to be already filtered by score.
+    pub fn update(&mut self, detections: &[detection::DetectionBox]) -> Vec<TrackedBox> {
+        self.frame_count += 1;
+        for track in self.tracks.iter_mut() {
+            track.predict();
+        }
+
+        let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+        for (track_idx, track) in self.tracks.iter().enumerate() {
+            for (detection_idx, detection) in detections.iter().enumerate() {
+                if track.detection.label != detection.label {
+                    continue;
+                }
+                let iou = track.detection.iou(detection);
+                if iou >= self.iou_threshold {
+                    candidates.push((iou, track_idx, detection_idx));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        let mut matched_detections = vec![false; detections.len()];
+        for (_, track_idx, detection_idx) in candidates {
+            if matched_tracks[track_idx] || matched_detections[detection_idx] {
+                continue;
+            }
+            self.tracks[track_idx].update(&detections[detection_idx]);
+            matched_tracks[track_idx] = true;
+            matched_detections[detection_idx] = true;
+        }
+
+        for (detection, matched) in detections.iter().zip(matched_detections) {
+            if !matched {
+                self.tracks.push(Track::new(self.next_id, detection));
+                self.next_id += 1;
+            }
+        }
+
+        let max_age = self.max_age;
+        self.tracks
+            .retain(|track| track.time_since_update <= max_age);
+
+        let warming_up = self.frame_count <= self.min_hits;
+        let min_hits = self.min_hits;
+        self.tracks
+            .iter()
+            .filter(|track| track.time_since_update == 0 && (track.hits >= min_hits || warming_up))
+            .map(|track| TrackedBox {
+                id: track.id,
+                detection: track.detection.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Tracker::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(x: f32, label: &str) -> detection::DetectionBox {
+        detection::DetectionBox {
+            x1: x,
+            y1: 0.4,
+            x2: x + 0.1,
+            y2: 0.5,
+            label: label.to_string(),
+            score: 0.9,
+        }
+    }
+
+    fn ids(tracked: &[TrackedBox]) -> Vec<u64> {
+        tracked.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn keeps_ids_of_moving_objects() {
+        let mut tracker = Tracker::new().min_hits(1);
+        for frame in 0..10 {
+            let x = frame as f32 * 0.02;
+            let tracked = tracker.update(&[detection(x, "car"), detection(0.8 - x, "person")]);
+            let mut tracked_ids = ids(&tracked);
+            tracked_ids.sort();
+            assert_eq!(tracked_ids, vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn does_not_match_different_labels() {
+        let mut tracker = Tracker::new().min_hits(1);
+        assert_eq!(ids(&tracker.update(&[detection(0.1, "car")])), vec![1]);
+        assert_eq!(ids(&tracker.update(&[detection(0.1, "truck")])), vec![2]);
+    }
+
+    #[test]
+    fn survives_occlusion_gaps() {
+        let mut tracker = Tracker::new().min_hits(1).max_age(2);
+        assert_eq!(ids(&tracker.update(&[detection(0.1, "car")])), vec![1]);
+        assert!(tracker.update(&[]).is_empty());
+        assert!(tracker.update(&[]).is_empty());
+        assert_eq!(ids(&tracker.update(&[detection(0.1, "car")])), vec![1]);
+
+        for _ in 0..3 {
+            tracker.update(&[]);
+        }
+        assert_eq!(ids(&tracker.update(&[detection(0.1, "car")])), vec![2]);
+    }
+
+    #[test]
+    fn requires_min_hits_after_warm_up() {
+        let mut tracker = Tracker::new().min_hits(2);
+        tracker.update(&[detection(0.1, "car")]);
+        tracker.update(&[detection(0.1, "car")]);
+        let tracked = tracker.update(&[detection(0.1, "car"), detection(0.5, "person")]);
+        assert_eq!(ids(&tracked), vec![1]);
+    }
+}
@@ -0,0 +1,452 @@
+use crate::detection;
+#[cfg(feature = "tensorflow")]
+use crate::drawing;
+use crate::error;
+#[cfg(feature = "tensorflow")]
+use crate::ssd_mobilenet;
+
+use image::GenericImageView;
+use std::io;
+use std::io::{Read, Write};
+use std::path;
+use std::process;
+use std::thread;
+use std::time;
+
+const FFMPEG: &str = "ffmpeg";
+const FFPROBE: &str = "ffprobe";
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+}
+
+impl VideoInfo {
+    // Returns a presentation timestamp of the frame with the given index.
+    pub fn timestamp(&self, index: u64) -> time::Duration {
+        if self.fps > 0.0 {
+            time::Duration::from_secs_f64(index as f64 / self.fps)
+        } else {
+            time::Duration::from_secs(0)
+        }
+    }
+}
+
+pub struct Frame {
+    pub index: u64,
+    pub timestamp: time::Duration,
+    pub image: image::DynamicImage,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct FrameDetections {
+    pub index: u64,
+    pub timestamp: time::Duration,
+    pub boxes: Vec<detection::DetectionBox>,
+}
+
+// Decodes video frames through an `ffmpeg` subprocess pipe.
+//
+// Any container and codec supported by the installed `ffmpeg` can be read. Frames are
+// decoded to RGB and yielded in presentation order. A decoding failure is returned as an
+// error after the last decoded frame. The rotation metadata of e.g. phone videos is not
+// applied, so the frames keep the coded size reported by `probe`.
+pub struct VideoReader {
+    child: process::Child,
+    // Collects the `ffmpeg` error messages, so a full stderr pipe never blocks the decoder.
+    stderr: Option<thread::JoinHandle<String>>,
+    info: VideoInfo,
+    index: u64,
+    finished: bool,
+}
+
+impl VideoReader {
+    pub fn open(video_path: &path::Path) -> Result<Self, error::Error> {
+        let info = probe(video_path)?;
+        let mut command = process::Command::new(FFMPEG);
+        command
+            .args(&["-v", "error", "-noautorotate", "-i"])
+            .arg(video_path)
+            .args(&["-f", "rawvideo", "-pix_fmt", "rgb24", "-"]);
+
+        VideoReader::spawn(command, info)
+    }
+
+    // Starts the decoder command writing raw RGB frames of the video to its stdout.
+    fn spawn(mut command: process::Command, info: VideoInfo) -> Result<Self, error::Error> {
+        let mut child = command
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()?;
+        let stderr = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut message = String::new();
+                let _ = stderr.read_to_string(&mut message);
+                message
+            })
+        });
+
+        Ok(VideoReader {
+            child,
+            stderr,
+            info,
+            index: 0,
+            finished: false,
+        })
+    }
+
+    pub fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>, error::Error> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let stdout = self
+            .child
+            .stdout
+            .as_mut()
+            .ok_or_else(|| "The ffmpeg output is not available")?;
+
+        let mut buffer = vec![0; self.info.width as usize * self.info.height as usize * 3];
+        match stdout.read_exact(&mut buffer) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return self.finish(),
+            Err(e) => {
+                self.finished = true;
+                return Err(e.into());
+            }
+        }
+
+        let image = image::ImageBuffer::from_raw(self.info.width, self.info.height, buffer)
+            .map(image::DynamicImage::ImageRgb8)
+            .ok_or_else(|| "Cannot contruct a frame from the decoded data")?;
+        let frame = Frame {
+            index: self.index,
+            timestamp: self.info.timestamp(self.index),
+            image,
+        };
+        self.index += 1;
+
+        Ok(Some(frame))
+    }
+
+    // Waits for the decoder at the end of its output and fails if it did not succeed.
+    fn finish(&mut self) -> Result<Option<Frame>, error::Error> {
+        self.finished = true;
+        std::mem::drop(self.child.stdout.take());
+        let status = self.child.wait()?;
+        let message = self
+            .stderr
+            .take()
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+
+        if !status.success() {
+            return Err(error::Error::IoError(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "ffmpeg failed to decode the video after {} frames: {}",
+                    self.index,
+                    message.trim()
+                ),
+            )));
+        }
+
+        Ok(None)
+    }
+}
+
+impl Iterator for VideoReader {
+    type Item = Result<Frame, error::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+impl Drop for VideoReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Encodes RGB frames into a video file through an `ffmpeg` subprocess pipe.
+pub struct VideoWriter {
+    child: process::Child,
+    width: u32,
+    height: u32,
+}
+
+impl VideoWriter {
+    pub fn create(video_path: &path::Path, info: &VideoInfo) -> Result<Self, error::Error> {
+        let child = process::Command::new(FFMPEG)
+            .args(&[
+                "-v", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s",
+            ])
+            .arg(format!("{}x{}", info.width, info.height))
+            .arg("-r")
+            .arg(format!("{}", info.fps))
+            .args(&[
+                "-i",
+                "-",
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .arg(video_path)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::piped())
+            .spawn()?;
+
+        Ok(VideoWriter {
+            child,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    // Appends a frame to the video. Frames of a different size are scaled to the video size.
+    pub fn write(&mut self, frame: &image::DynamicImage) -> Result<(), error::Error> {
+        let frame = if frame.dimensions() == (self.width, self.height) {
+            frame.to_rgb()
+        } else {
+            frame
+                .resize_exact(
+                    self.width,
+                    self.height,
+                    image::imageops::FilterType::Nearest,
+                )
+                .to_rgb()
+        };
+        let stdin = self
+            .child
+            .stdin
+            .as_mut()
+            .ok_or_else(|| "The ffmpeg input is not available")?;
+        stdin.write_all(&frame.into_raw())?;
+
+        Ok(())
+    }
+
+    // Flushes the remaining frames and waits for the encoder to finish.
+    pub fn finish(mut self) -> Result<(), error::Error> {
+        std::mem::drop(self.child.stdin.take());
+        let output = self.child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(error::Error::IoError(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "ffmpeg failed to encode the video: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+// Runs SSD detection over the frames of a video file.
+#[cfg(feature = "tensorflow")]
+pub struct VideoDetector<'a> {
+    ssd_net: &'a ssd_mobilenet::SSDMobileNetV2,
+    every_nth: u64,
+    annotate: Option<(path::PathBuf, drawing::ImageBoxes)>,
+}
+
+#[cfg(feature = "tensorflow")]
+impl<'a> VideoDetector<'a> {
+    pub fn new(ssd_net: &'a ssd_mobilenet::SSDMobileNetV2) -> Self {
+        VideoDetector {
+            ssd_net,
+            every_nth: 1,
+            annotate: None,
+        }
+    }
+
+    // Runs the detection on every Nth frame only.
+    pub fn every_nth(mut self, every_nth: u64) -> Self {
+        self.every_nth = every_nth.max(1);
+        self
+    }
+
+    // Writes an annotated copy of the video to `output_path`.
+    //
+    // Frames that are skipped by `every_nth` are drawn with the latest detected boxes. The boxes
+    // are drawn by `ImageBoxes::draw_rgba` at the original frame size, so the labels are only
+    // drawn when its font is set by `ImageBoxes::font_data`.
+    pub fn annotate(mut self, output_path: &path::Path, image_boxes: drawing::ImageBoxes) -> Self {
+        self.annotate = Some((output_path.to_path_buf(), image_boxes));
+        self
+    }
+
+    // Runs the detection and calls `on_frame` with the results of every processed frame.
+    pub fn run<F>(&self, video_path: &path::Path, mut on_frame: F) -> Result<(), error::Error>
+    where
+        F: FnMut(FrameDetections) -> Result<(), error::Error>,
+    {
+        let reader = VideoReader::open(video_path)?;
+        let mut writer = match self.annotate {
+            Some((ref output_path, _)) => Some(VideoWriter::create(output_path, reader.info())?),
+            None => None,
+        };
+
+        let mut boxes = Vec::new();
+        for frame in reader {
+            let frame = frame?;
+            if frame.index % self.every_nth == 0 {
+                boxes = self.ssd_net.shot(&frame.image)?;
+                on_frame(FrameDetections {
+                    index: frame.index,
+                    timestamp: frame.timestamp,
+                    boxes: boxes.clone(),
+                })?;
+            }
+
+            if let (Some(writer), Some((_, image_boxes))) =
+                (writer.as_mut(), self.annotate.as_ref())
+            {
+                let mut image = frame.image.to_rgba();
+                image_boxes.draw_rgba(&mut image, boxes.clone())?;
+                writer.write(&image::DynamicImage::ImageRgba8(image))?;
+            }
+        }
+
+        if let Some(writer) = writer {
+            writer.finish()?;
+        }
+
+        Ok(())
+    }
+
+    // Runs the detection and collects the results of every processed frame.
+    pub fn detect(&self, video_path: &path::Path) -> Result<Vec<FrameDetections>, error::Error> {
+        let mut detections = Vec::new();
+        self.run(video_path, |frame_detections| {
+            detections.push(frame_detections);
+            Ok(())
+        })?;
+
+        Ok(detections)
+    }
+}
+
+// Reads the size and the frame rate of the first video stream with `ffprobe`.
+pub fn probe(video_path: &path::Path) -> Result<VideoInfo, error::Error> {
+    let output = process::Command::new(FFPROBE)
+        .args(&[
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,r_frame_rate",
+            "-of",
+            "default=noprint_wrappers=1",
+        ])
+        .arg(video_path)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(error::Error::IoError(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "ffprobe failed to read \"{}\": {}",
+                video_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )));
+    }
+
+    parse_video_info(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_video_info(probe_output: &str) -> Result<VideoInfo, error::Error> {
+    let (mut width, mut height, mut fps) = (None, None, None);
+    for line in probe_output.lines() {
+        let mut parts = line.trim().splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("width"), Some(value)) => width = value.parse::<u32>().ok(),
+            (Some("height"), Some(value)) => height = value.parse::<u32>().ok(),
+            (Some("r_frame_rate"), Some(value)) => fps = parse_frame_rate(value),
+            _ => (),
+        }
+    }
+
+    match (width, height, fps) {
+        (Some(width), Some(height), Some(fps)) if width > 0 && height > 0 => {
+            Ok(VideoInfo { width, height, fps })
+        }
+        _ => Err(format!("Failed to parse the video stream info: {:?}", probe_output).into()),
+    }
+}
+
+fn parse_frame_rate(value: &str) -> Option<f64> {
+    let mut parts = value.splitn(2, '/');
+    let numerator = parts.next()?.trim().parse::<f64>().ok()?;
+    let denominator = match parts.next() {
+        Some(denominator) => denominator.trim().parse::<f64>().ok()?,
+        None => 1.0,
+    };
+
+    if denominator > 0.0 {
+        Some(numerator / denominator)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_probe_output() {
+        let info = parse_video_info("width=1280\nheight=720\nr_frame_rate=30000/1001\n").unwrap();
+        assert_eq!(info.width, 1280);
+        assert_eq!(info.height, 720);
+        assert!((info.fps - 29.97).abs() < 0.01);
+    }
+
+    #[test]
+    fn parse_invalid_probe_output() {
+        assert!(parse_video_info("width=1280\nr_frame_rate=0/0\n").is_err());
+    }
+
+    #[test]
+    fn frame_timestamp() {
+        let info = VideoInfo {
+            width: 2,
+            height: 2,
+            fps: 25.0,
+        };
+        assert_eq!(info.timestamp(50), time::Duration::from_secs(2));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fail_on_decoder_error() {
+        let info = VideoInfo {
+            width: 1,
+            height: 1,
+            fps: 25.0,
+        };
+        let mut command = process::Command::new("sh");
+        command.args(&["-c", "printf abcdef; echo 'Invalid data' >&2; exit 1"]);
+
+        let frames: Vec<_> = VideoReader::spawn(command, info).unwrap().collect();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].as_ref().unwrap().index, 1);
+        let err = frames[2].as_ref().err().unwrap().to_string();
+        assert!(err.contains("after 2 frames: Invalid data"), "{}", err);
+    }
+}